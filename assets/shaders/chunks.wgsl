@@ -0,0 +1,165 @@
+#import "shaders/core.wgsl"::{CHUNK_SIZE, TILE_SIZE, chunk_count, tile_count}
+
+// Must be kept in sync with `SLEEP_AFTER` in `src/chunk.rs`
+const SLEEP_AFTER: u32 = 8u;
+// How many tiles away a change may still have an effect on the next step
+const TILE_REACH: i32 = 4;
+const TILES_PER_CHUNK: u32 = CHUNK_SIZE / TILE_SIZE;
+
+// Arguments of an indirect dispatch over chunks, see `wgpu::util::DispatchIndirectArgs`
+struct DispatchArgs {
+    x: u32,
+    y: u32,
+    // One layer of workgroups per chunk
+    z: atomic<u32>,
+}
+
+// Read back for the perf counters, see `src/tile.rs`
+struct TileStats {
+    // Tiles stepped by the last step
+    awake: atomic<u32>,
+    // Tiles colored this frame
+    recolored: atomic<u32>,
+}
+
+@group(0) @binding(0)
+var<uniform> size : vec2<u32>; // width, height
+// Set by the step for each tile that something in changes
+@group(0) @binding(1)
+var<storage, read_write> tile_activity: array<atomic<u32>>;
+// Steps since something in each tile last changed, up to `SLEEP_AFTER`
+@group(0) @binding(2)
+var<storage, read_write> tile_quiet: array<u32>;
+@group(0) @binding(3)
+var<storage, read_write> tile_awake: array<u32>;
+@group(0) @binding(4)
+var<storage, read_write> awake_chunks: array<vec2<u32>>;
+@group(0) @binding(5)
+var<storage, read_write> dispatch: DispatchArgs;
+// Set for each tile whose cells have changed since it was last colored
+@group(0) @binding(6)
+var<storage, read_write> tile_colors: array<atomic<u32>>;
+@group(0) @binding(7)
+var<storage, read_write> color_chunks: array<vec2<u32>>;
+// Reset every frame by `prepare_color_uniform`
+@group(0) @binding(8)
+var<storage, read_write> color_dispatch: DispatchArgs;
+@group(0) @binding(9)
+var<storage, read_write> stats: TileStats;
+
+// ================================== Sleeping ================================== //
+//
+// A tile only needs to be stepped if something in it or around it has changed lately. Cells
+// that nothing has changed around keep on doing the same, except for the choices that cycle
+// with the step number (sinking every other step, reaction partners every four steps) and for
+// reactions that are rolled for, which keep their tile awake by themselves. So once a tile and
+// those around it have gone `SLEEP_AFTER` steps without a change, stepping it would leave it as
+// it is, and both of its buffers already hold the same cells.
+//
+// A cell only looks so far around itself in a step (falling, flowing up to `MAX_DISPERSION`
+// cells and the claims on those), which `TILE_REACH` tiles cover, so a sleeping tile is woken
+// up in time by a change around it.
+//
+// Steps are dispatched per chunk to keep the dispatch list short, over the chunks with an awake
+// tile in them, and the sleeping tiles in those return straight away.
+
+fn tile_offset(tile: vec2<u32>) -> u32 {
+    return tile.y * tile_count(size).x + tile.x;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn settle_tiles(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if all(global_invocation_id.xy == vec2(0u)) {
+        dispatch.x = TILES_PER_CHUNK;
+        dispatch.y = TILES_PER_CHUNK;
+        atomicStore(&dispatch.z, 0u);
+        atomicStore(&stats.awake, 0u);
+    }
+    if any(global_invocation_id.xy >= tile_count(size)) {
+        return;
+    }
+    let index = tile_offset(global_invocation_id.xy);
+
+    if atomicExchange(&tile_activity[index], 0u) != 0u {
+        tile_quiet[index] = 0u;
+    } else {
+        tile_quiet[index] = min(tile_quiet[index] + 1u, SLEEP_AFTER);
+    }
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn plan_tiles(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let tiles = tile_count(size);
+    if any(global_invocation_id.xy >= tiles) {
+        return;
+    }
+    let tile = vec2<i32>(global_invocation_id.xy);
+    let first = max(tile - vec2(TILE_REACH), vec2(0));
+    let last = min(tile + vec2(TILE_REACH), vec2<i32>(tiles) - 1);
+
+    var is_awake = false;
+    for (var y = first.y; y <= last.y; y++) {
+        for (var x = first.x; x <= last.x; x++) {
+            if tile_quiet[tile_offset(vec2<u32>(vec2(x, y)))] < SLEEP_AFTER {
+                is_awake = true;
+            }
+        }
+    }
+    tile_awake[tile_offset(global_invocation_id.xy)] = u32(is_awake);
+    if is_awake {
+        atomicAdd(&stats.awake, 1u);
+    }
+}
+
+// The tiles of `chunk`, the last chunks cut off where the grid ends
+fn chunk_tiles(chunk: vec2<u32>) -> vec4<u32> {
+    let first = chunk * TILES_PER_CHUNK;
+    return vec4(first, min(first + TILES_PER_CHUNK, tile_count(size)));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn plan_chunks(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if any(global_invocation_id.xy >= chunk_count(size)) {
+        return;
+    }
+    let tiles = chunk_tiles(global_invocation_id.xy);
+
+    var is_awake = false;
+    for (var y = tiles.y; y < tiles.w; y++) {
+        for (var x = tiles.x; x < tiles.z; x++) {
+            if tile_awake[tile_offset(vec2(x, y))] != 0u {
+                is_awake = true;
+            }
+        }
+    }
+    if is_awake {
+        awake_chunks[atomicAdd(&dispatch.z, 1u)] = global_invocation_id.xy;
+    }
+}
+
+// ================================== Coloring ================================== //
+//
+// The texture keeps what it was colored with, so only the tiles whose cells have changed since
+// are colored again. They are marked by whatever changes the cells (`init`, `update` and the
+// draw pass) and cleared by `color_cells`.
+
+@compute @workgroup_size(8, 8, 1)
+fn plan_colors(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if any(global_invocation_id.xy >= chunk_count(size)) {
+        return;
+    }
+    let tiles = chunk_tiles(global_invocation_id.xy);
+
+    var dirty = 0u;
+    for (var y = tiles.y; y < tiles.w; y++) {
+        for (var x = tiles.x; x < tiles.z; x++) {
+            if atomicLoad(&tile_colors[tile_offset(vec2(x, y))]) != 0u {
+                dirty += 1u;
+            }
+        }
+    }
+    if dirty > 0u {
+        color_chunks[atomicAdd(&color_dispatch.z, 1u)] = global_invocation_id.xy;
+        atomicAdd(&stats.recolored, dirty);
+    }
+}
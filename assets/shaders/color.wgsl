@@ -1,5 +1,10 @@
 
-#import "shaders/core.wgsl"::{Cell}
+#import litterbox::cell::Cell
+#import "shaders/core.wgsl"::{AMBIENT_TEMPERATURE, CHUNK_SIZE, Material, tile_index}
+
+struct ColorParams {
+    show_heatmap: u32,
+}
 
 @group(0) @binding(0) 
 var<uniform> size : vec2<u32>; // width, height
@@ -9,6 +14,23 @@ var<storage, read_write> input: array<Cell>;
 var<storage, read_write> output: array<Cell>;
 @group(0) @binding(3)
 var texture: texture_storage_2d<rgba8unorm, read_write>;
+@group(0) @binding(4)
+var<storage, read> materials: array<Material>;
+@group(0) @binding(5)
+var<uniform> params: ColorParams;
+// Set for each tile whose cells have changed since it was last colored
+@group(0) @binding(6)
+var<storage, read_write> tile_colors: array<atomic<u32>>;
+// The chunks with tiles to color, see `plan_colors` in `chunks.wgsl`
+@group(0) @binding(7)
+var<storage, read> color_chunks: array<vec2<u32>>;
+
+// Whether the tile of a workgroup is to be colored, the same for all of its invocations
+var<workgroup> is_dirty: u32;
+
+// Range of temperatures the heatmap tells apart
+const HEATMAP_MIN: f32 = -50.;
+const HEATMAP_MAX: f32 = 1500.;
 
 fn idx(location: vec2<i32>) -> i32 {
     return location.y * i32(size.x) + location.x;
@@ -18,12 +40,45 @@ fn get_cell(location: vec2<i32>) -> Cell {
     return input[idx(location)];
 }
 
+// Blue below ambient temperature, then black, red, yellow and white as it gets hotter
+fn heatmap(temperature: f32) -> vec4<f32> {
+    if temperature < AMBIENT_TEMPERATURE {
+        let cold = clamp((AMBIENT_TEMPERATURE - temperature) / (AMBIENT_TEMPERATURE - HEATMAP_MIN), 0., 1.);
+        return vec4(0., 0., cold, 1.);
+    }
+    // The square root makes small differences around ambient temperature visible
+    let heat = 3. * sqrt(clamp((temperature - AMBIENT_TEMPERATURE) / (HEATMAP_MAX - AMBIENT_TEMPERATURE), 0., 1.));
+    return vec4(clamp(vec3(heat, heat - 1., heat - 2.), vec3(0.), vec3(1.)), 1.);
+}
+
+// Dispatched over the chunks with tiles to color, with a workgroup per tile
 @compute @workgroup_size(8, 8, 1)
-fn color_cells(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
-    var location = vec2<i32>(global_invocation_id.xy);
+fn color_cells(@builtin(global_invocation_id) global_invocation_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let location = vec2<i32>(color_chunks[global_invocation_id.z] * CHUNK_SIZE + global_invocation_id.xy);
+    // The first invocation is at the corner of the tile, which is in the grid if any of it is
+    if local_invocation_index == 0u {
+        is_dirty = 0u;
+        if all(location < vec2<i32>(size)) {
+            is_dirty = atomicExchange(&tile_colors[tile_index(location, size)], 0u);
+        }
+    }
+    // Tiles that haven't changed are still colored right, and chunks are cut off where the grid
+    // ends
+    if workgroupUniformLoad(&is_dirty) == 0u || any(location >= vec2<i32>(size)) {
+        return;
+    }
     let cell = get_cell(location);
+    if params.show_heatmap != 0u {
+        textureStore(texture, location, heatmap(cell.temperature));
+        return;
+    }
     var color = cell.color;
-    color.y = f32(global_invocation_id.y / size.y / 4);
+
+    // Fade out cells that are about to disappear
+    let lifetime = materials[cell.type_id].lifetime;
+    if lifetime > 0u {
+        color.a *= clamp(f32(cell.lifetime) / f32(lifetime), 0., 1.);
+    }
 
     textureStore(texture, location, color);
-}
\ No newline at end of file
+}
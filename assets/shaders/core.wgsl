@@ -1,12 +1,48 @@
-struct Cell {
-    type_id: i32,
-    /*
-    ID  Type
-    0   Air
-    1   Wall
-    2   Sand
-    */
+// `Cell` is generated from `src/cell.rs`
+#import litterbox::cell::Cell
+
+// Must be kept in sync with `MaterialState` in `src/material.rs`
+const STATE_EMPTY: u32 = 0u;
+const STATE_SOLID: u32 = 1u;
+const STATE_POWDER: u32 = 2u;
+const STATE_LIQUID: u32 = 3u;
+const STATE_GAS: u32 = 4u;
+
+// Upper bound on `Material::dispersion`
+const MAX_DISPERSION: i32 = 8;
+
+// Must be kept in sync with `MAX_MATERIALS` in `src/material.rs`
+const MAX_MATERIALS: i32 = 64;
+
+// Must be kept in sync with `AMBIENT_TEMPERATURE` in `src/material.rs`
+const AMBIENT_TEMPERATURE: f32 = 20.;
+
+// Must be kept in sync with `CHUNK_SIZE` in `src/chunk.rs`
+const CHUNK_SIZE: u32 = 64u;
+
+// Side of the tiles that activity is tracked for, one workgroup each. Must be kept in sync with
+// `WORKGROUP_SIZE` in `src/lib.rs`
+const TILE_SIZE: u32 = 8u;
+
+struct Material {
     color: vec4<f32>,
+    color_jitter: f32,
+    density: f32,
+    state: u32,
+    // What the cell turns into once its lifetime runs out
+    decays_into: i32,
+    // How many cells a liquid or gas may move sideways per step
+    dispersion: u32,
+    // Average number of steps a cell lasts, 0 if it lasts forever
+    lifetime: u32,
+    // Temperature new cells start at
+    temperature: f32,
+    conductivity: f32,
+    // Phase transitions, the `*_into` type ids are -1 if the material has none
+    heats_above: f32,
+    heats_into: i32,
+    cools_below: f32,
+    cools_into: i32,
 }
 
 fn hash(value: u32) -> u32 {
@@ -22,4 +58,33 @@ fn hash(value: u32) -> u32 {
 
 fn randomFloat(value: u32) -> f32 {
     return f32(hash(value)) / 4294967295.0;
-}
\ No newline at end of file
+}
+
+// Base color of a material, with each channel offset by up to its color jitter
+fn jitter_color(material: Material, random: f32) -> vec4<f32> {
+    let offset = (random * 2. - 1.) * material.color_jitter;
+    return vec4(clamp(material.color.rgb + vec3(offset), vec3(0.), vec3(1.)), material.color.a);
+}
+
+// A freshly placed cell of the given material
+fn spawn_cell(type_id: i32, material: Material, random: f32) -> Cell {
+    // Vary lifetimes by up to 25% so that gases don't vanish all at once
+    let lifetime = u32(f32(material.lifetime) * (0.75 + 0.5 * random));
+    return Cell(type_id, lifetime, material.temperature, jitter_color(material, random));
+}
+
+// Chunks covering a grid of `size` cells, the last ones cut off where the grid ends
+fn chunk_count(size: vec2<u32>) -> vec2<u32> {
+    return (size + vec2(CHUNK_SIZE - 1u)) / CHUNK_SIZE;
+}
+
+// Tiles covering a grid of `size` cells, the last ones cut off where the grid ends
+fn tile_count(size: vec2<u32>) -> vec2<u32> {
+    return (size + vec2(TILE_SIZE - 1u)) / TILE_SIZE;
+}
+
+// Index into the per tile buffers of the tile that the cell at `location` is in
+fn tile_index(location: vec2<i32>, size: vec2<u32>) -> u32 {
+    let tile = vec2<u32>(location) / TILE_SIZE;
+    return tile.y * tile_count(size).x + tile.x;
+}
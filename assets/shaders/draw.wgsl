@@ -0,0 +1,59 @@
+#import litterbox::cell::Cell
+#import "shaders/core.wgsl"::{Material, randomFloat, spawn_cell, tile_index}
+
+struct DrawParams {
+    start: vec2<f32>,
+    end: vec2<f32>,
+    radius: f32,
+    type_id: i32,
+}
+
+@group(0) @binding(0) 
+var<uniform> size : vec2<u32>; // width, height
+@group(0) @binding(1)
+var<uniform> draw: DrawParams;
+@group(0) @binding(2) 
+var<storage, read_write> cells: array<Cell>;
+@group(0) @binding(3)
+var<storage, read> materials: array<Material>;
+// Wakes the tiles that are painted into, see `chunks.wgsl`
+@group(0) @binding(4)
+var<storage, read_write> tile_activity: array<atomic<u32>>;
+// and has them colored again, see `color.wgsl`
+@group(0) @binding(5)
+var<storage, read_write> tile_colors: array<atomic<u32>>;
+
+fn idx(location: vec2<i32>) -> i32 {
+    return location.y * i32(size.x) + location.x;
+}
+
+// Distance from a point to the segment between start and end, so that fast
+// strokes are filled in rather than leaving a trail of separate dots
+fn distance_to_segment(point: vec2<f32>, start: vec2<f32>, end: vec2<f32>) -> f32 {
+    let segment = end - start;
+    let length_squared = dot(segment, segment);
+    if length_squared == 0. {
+        return distance(point, start);
+    }
+    let t = clamp(dot(point - start, segment) / length_squared, 0., 1.);
+    return distance(point, start + t * segment);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn draw_cells(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    // Dispatches are rounded up to whole workgroups, past the edges there is nothing to do
+    if any(global_invocation_id.xy >= size) {
+        return;
+    }
+    let location = vec2<i32>(global_invocation_id.xy);
+    let center = vec2<f32>(location) + vec2(0.5);
+
+    if distance_to_segment(center, draw.start, draw.end) > draw.radius {
+        return;
+    }
+
+    let random = randomFloat(u32(idx(location)));
+    cells[idx(location)] = spawn_cell(draw.type_id, materials[draw.type_id], random);
+    atomicStore(&tile_activity[tile_index(location, size)], 1u);
+    atomicStore(&tile_colors[tile_index(location, size)], 1u);
+}
@@ -1,4 +1,23 @@
-#import "shaders/core.wgsl"::{Cell, randomFloat}
+#import litterbox::cell::Cell
+#import "shaders/core.wgsl"::{CHUNK_SIZE, Material, MAX_DISPERSION, MAX_MATERIALS, STATE_EMPTY, STATE_GAS, STATE_LIQUID, STATE_POWDER, hash, randomFloat, spawn_cell, tile_index}
+
+// One side of a reaction, see `GpuReaction` in `src/reaction.rs`
+struct Reaction {
+    // 0 if the pair doesn't react
+    chance: f32,
+    // -1 to stay the same
+    into: i32,
+}
+
+// See `InitUniform` and `InitPattern` in `src/pipeline/automata.rs`
+struct InitParams {
+    wall: i32,
+    sand: i32,
+    pattern: u32,
+}
+
+const PATTERN_SAND: u32 = 0u;
+const PATTERN_EMPTY: u32 = 2u;
 
 @group(0) @binding(0) 
 var<uniform> size : vec2<u32>; // width, height
@@ -6,84 +25,431 @@ var<uniform> size : vec2<u32>; // width, height
 var<storage, read_write> input: array<Cell>;
 @group(0) @binding(2)
 var<storage, read_write> output: array<Cell>;
+@group(0) @binding(3)
+var<storage, read> materials: array<Material>;
+@group(0) @binding(4)
+var<uniform> init_params: InitParams;
+@group(0) @binding(5)
+var<uniform> frame: u32;
+// Written by `claim`, read by `update`
+@group(0) @binding(6)
+var<storage, read_write> intents: array<vec2<i32>>;
+// Written by `diffuse_heat`, read by `update`
+@group(0) @binding(7)
+var<storage, read_write> temperatures: array<f32>;
+// Indexed by `type_id * MAX_MATERIALS + neighbor_type_id`
+@group(0) @binding(8)
+var<storage, read> reactions: array<Reaction>;
+// Written by `react`, read by `update`: the type id each cell turns into, -1 to stay the same
+@group(0) @binding(9)
+var<storage, read_write> products: array<i32>;
+// Set for each tile that something in changes, see `settle_tiles` in `chunks.wgsl`
+@group(0) @binding(10)
+var<storage, read_write> tile_activity: array<atomic<u32>>;
+// The chunks that this step is dispatched over, see `plan_chunks` in `chunks.wgsl`
+@group(0) @binding(11)
+var<storage, read> awake_chunks: array<vec2<u32>>;
+// Non-zero for the tiles in those chunks that are stepped, see `plan_tiles` in `chunks.wgsl`
+@group(0) @binding(12)
+var<storage, read> tile_awake: array<u32>;
+// Set for each tile that needs to be colored again, see `color.wgsl`
+@group(0) @binding(13)
+var<storage, read_write> tile_colors: array<atomic<u32>>;
+// Seeds every random choice, see `SimulationSeed` in `src/lib.rs`
+@group(0) @binding(14)
+var<uniform> seed: u32;
+
+const NO_MOVE = vec2(0, 0);
+const GAS_DRIFT_CHANCE: f32 = 0.3;
 
 fn idx(location: vec2<i32>) -> i32 {
     return location.y * i32(size.x) + location.x;
 }
 
-fn get_cell(location: vec2<i32>, offset_x: i32, offset_y: i32) -> Cell {
-    let loc = ((location + vec2<i32>(offset_x, offset_y)) + vec2<i32>(size)) % vec2<i32>(size);
-    return input[idx(location + vec2<i32>(offset_x, offset_y))];
+fn in_bounds(location: vec2<i32>) -> bool {
+    return all(location >= vec2(0)) && all(location < vec2<i32>(size));
+}
+
+// The cell that an invocation of a step works on. Steps are dispatched over the awake chunks,
+// with a layer of workgroups for each of them.
+fn step_location(global_invocation_id: vec3<u32>) -> vec2<i32> {
+    let chunk = awake_chunks[global_invocation_id.z];
+    return vec2<i32>(chunk * CHUNK_SIZE + global_invocation_id.xy);
+}
+
+// Whether the cell at `location` is in the grid and in a tile that is stepped
+fn is_stepped(location: vec2<i32>) -> bool {
+    return in_bounds(location) && tile_awake[tile_index(location, size)] != 0u;
+}
+
+// Keeps the tile that `location` is in, and those around it, awake for the next steps
+fn wake(location: vec2<i32>) {
+    atomicStore(&tile_activity[tile_index(location, size)], 1u);
+}
+
+// Has the tile that `location` is in colored again
+fn recolor(location: vec2<i32>) {
+    atomicStore(&tile_colors[tile_index(location, size)], 1u);
+}
+
+fn get_cell(location: vec2<i32>) -> Cell {
+    return input[idx(location)];
+}
+
+fn state_of(cell: Cell) -> u32 {
+    return materials[cell.type_id].state;
+}
+
+fn is_empty(location: vec2<i32>) -> bool {
+    return in_bounds(location) && state_of(get_cell(location)) == STATE_EMPTY;
+}
+
+// Random number in [0, 1] that differs per cell, per step and per salt, but is the same for
+// every invocation that asks about the same cell
+fn cell_random(location: vec2<i32>, salt: u32) -> f32 {
+    return randomFloat(u32(idx(location)) ^ hash(frame ^ hash(salt ^ hash(seed))));
+}
+
+// Picks -1 (left) or 1 (right)
+fn random_side(location: vec2<i32>) -> i32 {
+    return select(1, -1, cell_random(location, 0u) < 0.5);
 }
 
 @compute @workgroup_size(8, 8, 1)
 fn init(@builtin(global_invocation_id) global_invocation_id: vec3<u32>, @builtin(num_workgroups) num_workgroups: vec3<u32>, @builtin(workgroup_id) workgroup_id: vec3<u32>) {
+    // Dispatches are rounded up to whole workgroups, past the edges there is nothing to do
+    if any(global_invocation_id.xy >= size) {
+        return;
+    }
     let location = vec2<i32>(global_invocation_id.xy);
 
-    let randomNumber = randomFloat(global_invocation_id.y * num_workgroups.x + global_invocation_id.x + workgroup_id.x + workgroup_id.y + workgroup_id.z);
+    let randomNumber = randomFloat((global_invocation_id.y * num_workgroups.x + global_invocation_id.x + workgroup_id.x + workgroup_id.y + workgroup_id.z) ^ hash(seed));
     var type_id = 0;
-    var color = vec4(0., 0., 0., 1.);
 
     // Not sure where this number comes from (divide by 1.2?) comes from but it works
-    if (global_invocation_id.y == (size.y / 2) - 1) || (global_invocation_id.x == 0 || global_invocation_id.x == size.x - 1) {
-        type_id = 1;
-        color.x = 1.;
+    if init_params.pattern == PATTERN_EMPTY {
+        type_id = 0;
+    }
+    else if (global_invocation_id.y == (size.y / 2) - 1) || (global_invocation_id.x == 0 || global_invocation_id.x == size.x - 1) {
+        type_id = init_params.wall;
     }
-    else if randomNumber > 0.9 {
-        type_id = 2;
-        color.z = 1.;
+    else if init_params.pattern == PATTERN_SAND && randomNumber > 0.9 {
+        type_id = init_params.sand;
     }
 
-    input[idx(location)] = Cell(i32(type_id), color);
+    input[idx(location)] = spawn_cell(type_id, materials[type_id], randomFloat(u32(idx(location)) ^ hash(seed)));
+    recolor(location);
 }
 
-@compute @workgroup_size(8, 8, 1)
-fn update(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
-    let location = vec2<i32>(global_invocation_id.xy);
-    // let num_neighbors = count_neighbors_simple(location);
-    let cell = get_cell(location, 0, 0);
-
-    var result: Cell = cell;
-    switch cell.type_id {
-        // Treat default as id=0 (Air)
-        case 0, default {
-            let above = get_cell(location, 0, -1);
-            if above.type_id == 2 {
-                result = Cell(2, above.color);
-            } else if above.type_id == 0 {
-                let above_right = get_cell(location, 1, -1);
-                if above_right.type_id == 2 {
-                    result = Cell(2, above_right.color);
-                } else {
-                    let above_left = get_cell(location, -1, -1);
-                    if above_left.type_id == 2 {
-                        result = Cell(2, above_left.color);
-                    }
-                }
-            }
+// ================================== Movement ================================== //
+//
+// Movement happens in two passes over the grid, so that mass is conserved:
+//
+// 1. `claim`: each cell decides where it wants to move (its intent) from the input buffer
+//    alone and records it in `intents`. Movers only ever target empty cells.
+// 2. `update`: an empty cell targeted by several movers picks one of them (`claimant`) in a
+//    fixed priority order, and the winner and the empty cell swap. Since every invocation
+//    can look up every intent, the mover and its target agree on the outcome even though
+//    each invocation only writes its own cell, so nothing is duplicated or lost.
+//
+// On top of that, a powder or fluid resting on a lighter fluid sinks by swapping with it.
+// To keep those swaps disjoint, only rows whose parity matches the step may sink.
+
+// Straight down (or up for dir_y = -1), then diagonally
+fn fall_intent(location: vec2<i32>, dir_y: i32) -> vec2<i32> {
+    if is_empty(location + vec2(0, dir_y)) {
+        return vec2(0, dir_y);
+    }
+    let side = random_side(location);
+    if is_empty(location + vec2(side, dir_y)) {
+        return vec2(side, dir_y);
+    }
+    if is_empty(location + vec2(-side, dir_y)) {
+        return vec2(-side, dir_y);
+    }
+    return NO_MOVE;
+}
+
+// How many empty cells there are in a row next to `location`, up to `dispersion`
+fn flow_reach(location: vec2<i32>, side: i32, dispersion: i32) -> i32 {
+    var reach = 0;
+    for (var distance = 1; distance <= dispersion; distance++) {
+        if !is_empty(location + vec2(side * distance, 0)) {
+            break;
+        }
+        reach = distance;
+    }
+    return reach;
+}
+
+// Sideways, as far as the material's dispersion allows
+fn flow_intent(location: vec2<i32>, dispersion: i32) -> vec2<i32> {
+    let side = random_side(location);
+    let reach = flow_reach(location, side, dispersion);
+    if reach > 0 {
+        return vec2(side * reach, 0);
+    }
+    return vec2(-side * flow_reach(location, -side, dispersion), 0);
+}
+
+fn intent(location: vec2<i32>) -> vec2<i32> {
+    let material = materials[get_cell(location).type_id];
+    switch material.state {
+        case STATE_POWDER {
+            return fall_intent(location, 1);
         }
-        // Wall
-        case 1 {
-            result = cell;
+        case STATE_LIQUID {
+            let fall = fall_intent(location, 1);
+            if any(fall != NO_MOVE) {
+                return fall;
+            }
+            return flow_intent(location, min(i32(material.dispersion), MAX_DISPERSION));
         }
-        // Sand
-        case 2 {
-            let below = get_cell(location, 0, 1);
-            if below.type_id == 0 {
-                result = Cell(0, vec4(0.,0.,0.,1.));
-            } else {
-                let below_left = get_cell(location, -1, 1);
-                if below_left.type_id == 0 {
-                    result = Cell(0, vec4(0.,0.,0.,1.));
-                } else {
-                    let below_right = get_cell(location, 1, 1);
-                    if below_right.type_id == 0 {
-                        result = Cell(0, vec4(0,0.,0.,1.));
-                    }
-                }
+        case STATE_GAS {
+            // Rise, but every now and then drift sideways instead
+            let drift = flow_intent(location, min(i32(material.dispersion), MAX_DISPERSION));
+            if cell_random(location, 1u) < GAS_DRIFT_CHANCE && any(drift != NO_MOVE) {
+                return drift;
+            }
+            let rise = fall_intent(location, -1);
+            if any(rise != NO_MOVE) {
+                return rise;
             }
+            return drift;
+        }
+        default {
+            return NO_MOVE;
+        }
+    }
+}
+
+fn intent_at(location: vec2<i32>) -> vec2<i32> {
+    return intents[idx(location)];
+}
+
+fn claims(destination: vec2<i32>, offset: vec2<i32>) -> bool {
+    let source = destination + offset;
+    return in_bounds(source) && all(intent_at(source) == -offset);
+}
+
+// Offset from the empty cell at `location` to the mover that gets to move into it,
+// or NO_MOVE if nothing does
+fn claimant(location: vec2<i32>) -> vec2<i32> {
+    // Alternate which side wins ties so that piles and puddles don't drift one way
+    let side = select(-1, 1, frame % 2u == 0u);
+    // Falling first, then rising, then sideways
+    var candidates = array(
+        vec2(0, -1), vec2(side, -1), vec2(-side, -1),
+        vec2(0, 1), vec2(side, 1), vec2(-side, 1),
+    );
+    for (var i = 0; i < 6; i++) {
+        if claims(location, candidates[i]) {
+            return candidates[i];
+        }
+    }
+    for (var distance = 1; distance <= MAX_DISPERSION; distance++) {
+        if claims(location, vec2(side * distance, 0)) {
+            return vec2(side * distance, 0);
+        }
+        if claims(location, vec2(-side * distance, 0)) {
+            return vec2(-side * distance, 0);
         }
     }
+    return NO_MOVE;
+}
+
+fn is_fluid(state: u32) -> bool {
+    return state == STATE_LIQUID || state == STATE_GAS;
+}
+
+// Whether the cell at `location` swaps places with a lighter fluid right below it.
+// Gases rise through liquids this way too, by the liquid sinking through the gas.
+fn sinks(location: vec2<i32>) -> bool {
+    let below = location + vec2(0, 1);
+    if !in_bounds(location) || !in_bounds(below) || (u32(location.y) + frame) % 2u != 0u {
+        return false;
+    }
+    let material = materials[get_cell(location).type_id];
+    let below_material = materials[get_cell(below).type_id];
+    return (material.state == STATE_POWDER || is_fluid(material.state))
+        && is_fluid(below_material.state)
+        && material.density > below_material.density
+        && all(intent_at(location) == NO_MOVE)
+        && all(intent_at(below) == NO_MOVE);
+}
+
+// Counts down the lifetime of cells that don't last forever
+fn age(cell: Cell, random: f32) -> Cell {
+    let material = materials[cell.type_id];
+    if material.lifetime == 0u {
+        return cell;
+    }
+    if cell.lifetime <= 1u {
+        var decayed = spawn_cell(material.decays_into, materials[material.decays_into], random);
+        decayed.temperature = cell.temperature;
+        return decayed;
+    }
+    var aged = cell;
+    aged.lifetime -= 1u;
+    return aged;
+}
+
+// ================================== Reactions ================================== //
+//
+// Reactions are listed in `litterbox.reactions.ron` and looked up by pair of materials in
+// `reactions`. To keep them conflict free, every step pairs each cell with exactly one of its
+// neighbors and only the two cells of a pair may react with each other. Both cells of a pair
+// roll the same random number, so they agree on whether the reaction happens. The pairing
+// cycles through all four directions every four steps.
+//
+// `react` only records what each cell turns into; `update` applies it as the cell moves.
+
+fn reaction_partner(location: vec2<i32>) -> vec2<i32> {
+    let is_vertical = frame % 2u == 1u;
+    let coordinate = select(location.x, location.y, is_vertical);
+    let offset = select(-1, 1, (u32(coordinate) + frame / 2u) % 2u == 0u);
+    return location + select(vec2(offset, 0), vec2(0, offset), is_vertical);
+}
+
+fn reaction(type_id: i32, neighbor: i32) -> Reaction {
+    return reactions[type_id * MAX_MATERIALS + neighbor];
+}
 
+@compute @workgroup_size(8, 8, 1)
+fn react(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    // Chunks are cut off where the grid ends, and the tiles in them that sleep are left alone
+    let location = step_location(global_invocation_id);
+    if !is_stepped(location) {
+        return;
+    }
+    let partner = reaction_partner(location);
+
+    var product = -1;
+    if in_bounds(partner) {
+        let first = select(partner, location, idx(location) < idx(partner));
+        let reaction = reaction(get_cell(location).type_id, get_cell(partner).type_id);
+        // Whether or not it happens this step, so that the pair doesn't fall asleep
+        if reaction.chance > 0. {
+            wake(location);
+        }
+        if cell_random(first, 3u) < reaction.chance {
+            product = reaction.into;
+        }
+    }
+    products[idx(location)] = product;
+}
+
+// ================================== Heat ================================== //
+//
+// Heat flows between neighboring cells in proportion to their temperature difference and the
+// lower conductivity of the two, so whatever one cell gains its neighbor loses. `diffuse_heat`
+// writes the new temperatures to `temperatures` and `update` carries them along with the
+// cells. A cell that ends up past one of its material's thresholds then changes phase.
+
+// Heat flowing into the cell at `location` from `neighbor`
+fn heat_flow(location: vec2<i32>, neighbor: vec2<i32>) -> f32 {
+    if !in_bounds(neighbor) {
+        return 0.;
+    }
+    let cell = get_cell(location);
+    let other = get_cell(neighbor);
+    let conductivity = min(materials[cell.type_id].conductivity, materials[other.type_id].conductivity);
+    return conductivity * (other.temperature - cell.temperature);
+}
+
+fn change_phase(cell: Cell, random: f32) -> Cell {
+    let material = materials[cell.type_id];
+    var type_id = -1;
+    if material.heats_into >= 0 && cell.temperature > material.heats_above {
+        type_id = material.heats_into;
+    } else if material.cools_into >= 0 && cell.temperature < material.cools_below {
+        type_id = material.cools_into;
+    }
+    if type_id < 0 {
+        return cell;
+    }
+    var result = spawn_cell(type_id, materials[type_id], random);
+    result.temperature = cell.temperature;
+    return result;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn diffuse_heat(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    // Chunks are cut off where the grid ends, and the tiles in them that sleep are left alone
+    let location = step_location(global_invocation_id);
+    if !is_stepped(location) {
+        return;
+    }
+    temperatures[idx(location)] = get_cell(location).temperature
+        + heat_flow(location, location + vec2(0, -1))
+        + heat_flow(location, location + vec2(1, 0))
+        + heat_flow(location, location + vec2(0, 1))
+        + heat_flow(location, location + vec2(-1, 0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn claim(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    // Chunks are cut off where the grid ends, and the tiles in them that sleep are left alone
+    let location = step_location(global_invocation_id);
+    if !is_stepped(location) {
+        return;
+    }
+    intents[idx(location)] = intent(location);
+}
+
+// Where the cell that ends up at `location` after this step comes from
+fn source_of(location: vec2<i32>) -> vec2<i32> {
+    if state_of(get_cell(location)) == STATE_EMPTY {
+        return location + claimant(location);
+    }
+    let heading = intent_at(location);
+    if any(heading != NO_MOVE) {
+        // Only move if we won the target, leaving the empty cell behind
+        if all(claimant(location + heading) == -heading) {
+            return location + heading;
+        }
+        return location;
+    }
+    if sinks(location) {
+        return location + vec2(0, 1);
+    }
+    if sinks(location + vec2(0, -1)) {
+        return location + vec2(0, -1);
+    }
+    return location;
+}
+
+// The cell that ends up at `location` after moving, with its new temperature and material
+fn moved_cell(location: vec2<i32>) -> Cell {
+    let source = source_of(location);
+    let product = products[idx(source)];
+    if product >= 0 {
+        // Reaction products start out at their own temperature, which is how fire gives off heat
+        return spawn_cell(product, materials[product], cell_random(source, 4u));
+    }
+    var cell = get_cell(source);
+    cell.temperature = temperatures[idx(source)];
+    return cell;
+}
+
+fn is_same_cell(a: Cell, b: Cell) -> bool {
+    return a.type_id == b.type_id && a.lifetime == b.lifetime && a.temperature == b.temperature
+        && all(a.color == b.color);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn update(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    // Chunks are cut off where the grid ends, and the tiles in them that sleep are left alone
+    let location = step_location(global_invocation_id);
+    if !is_stepped(location) {
+        return;
+    }
+
+    let aged = age(moved_cell(location), cell_random(location, 5u));
+    let result = change_phase(aged, cell_random(location, 2u));
+    if !is_same_cell(result, get_cell(location)) {
+        wake(location);
+        recolor(location);
+    }
     output[idx(location)] = result;
-}
\ No newline at end of file
+}
@@ -1,18 +1,145 @@
+use bevy::{asset::Handle, render::render_resource::Shader};
 use bytemuck::{Pod, Zeroable};
+use std::mem::{offset_of, size_of};
 
-#[derive(Clone, Copy, Zeroable, Pod)]
+use crate::material::AMBIENT_TEMPERATURE;
+
+/// Shader module declaring the WGSL side of [`Cell`], imported with
+/// `#import litterbox::cell::Cell`. Its source is generated by [`wgsl_module`].
+pub const CELL_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x6c69_7474_6572_626f_785f_6365_6c6c_0001);
+
+/// A single cell of the grid, as stored in `GameOfLifeBuffers::in_out`.
+///
+/// WGSL aligns `vec4<f32>` to 16 bytes, so `temperature` is followed by explicit padding to keep
+/// this struct byte-compatible with the shader's view of it. See [`WGSL_FIELDS`].
+#[derive(Clone, Copy, Debug, Zeroable, Pod)]
 #[repr(C)]
 pub struct Cell {
-    pub alive: u32,
+    /// Index into the material table, see `assets/litterbox.materials.ron`.
+    pub type_id: i32,
+    /// Steps left before the cell turns back into the empty material, for materials with a
+    /// limited lifetime (gases).
+    pub lifetime: u32,
+    /// In degrees Celsius.
+    pub temperature: f32,
+    pub _padding: u32,
     pub color: [f32; 4],
 }
 
 impl Default for Cell {
     fn default() -> Self {
         Self {
-            alive: 0,
+            type_id: 0,
+            lifetime: 0,
+            temperature: AMBIENT_TEMPERATURE,
+            _padding: 0,
             color: [0., 0., 0., 1.],
         }
     }
 }
 
+#[derive(Clone, Copy)]
+enum WgslType {
+    I32,
+    U32,
+    F32,
+    Vec4F32,
+}
+
+impl WgslType {
+    const fn name(self) -> &'static str {
+        match self {
+            WgslType::I32 => "i32",
+            WgslType::U32 => "u32",
+            WgslType::F32 => "f32",
+            WgslType::Vec4F32 => "vec4<f32>",
+        }
+    }
+
+    // https://www.w3.org/TR/WGSL/#alignment-and-size
+    const fn size(self) -> usize {
+        match self {
+            WgslType::I32 | WgslType::U32 | WgslType::F32 => 4,
+            WgslType::Vec4F32 => 16,
+        }
+    }
+
+    const fn align(self) -> usize {
+        match self {
+            WgslType::I32 | WgslType::U32 | WgslType::F32 => 4,
+            WgslType::Vec4F32 => 16,
+        }
+    }
+}
+
+struct WgslField {
+    name: &'static str,
+    ty: WgslType,
+    /// Offset of the matching field in the Rust struct.
+    offset: usize,
+}
+
+/// The single source of truth for the WGSL `Cell` struct, in declaration order. Padding
+/// fields are left out, WGSL inserts that padding implicitly.
+const WGSL_FIELDS: [WgslField; 4] = [
+    WgslField {
+        name: "type_id",
+        ty: WgslType::I32,
+        offset: offset_of!(Cell, type_id),
+    },
+    WgslField {
+        name: "lifetime",
+        ty: WgslType::U32,
+        offset: offset_of!(Cell, lifetime),
+    },
+    WgslField {
+        name: "temperature",
+        ty: WgslType::F32,
+        offset: offset_of!(Cell, temperature),
+    },
+    WgslField {
+        name: "color",
+        ty: WgslType::Vec4F32,
+        offset: offset_of!(Cell, color),
+    },
+];
+
+const fn round_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+/// Lays out `fields` following WGSL's rules for storage buffer structs and checks that every
+/// field lands on the same offset as in Rust, and that both structs have the same size.
+const fn matches_wgsl_layout(fields: &[WgslField], size: usize) -> bool {
+    let mut offset = 0;
+    let mut align = 1;
+    let mut i = 0;
+    while i < fields.len() {
+        let ty = fields[i].ty;
+        offset = round_up(offset, ty.align());
+        if offset != fields[i].offset {
+            return false;
+        }
+        offset += ty.size();
+        if ty.align() > align {
+            align = ty.align();
+        }
+        i += 1;
+    }
+    round_up(offset, align) == size
+}
+
+const _: () = assert!(
+    matches_wgsl_layout(&WGSL_FIELDS, size_of::<Cell>()),
+    "`Cell` does not match the layout WGSL gives its fields"
+);
+
+/// Generates the shader module registered under [`CELL_SHADER_HANDLE`].
+pub fn wgsl_module() -> String {
+    let fields: String = WGSL_FIELDS
+        .iter()
+        .map(|field| format!("    {}: {},\n", field.name, field.ty.name()))
+        .collect();
+    format!("#define_import_path litterbox::cell\n\nstruct Cell {{\n{fields}}}\n")
+}
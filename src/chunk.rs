@@ -0,0 +1,364 @@
+//! Splitting the grid into chunks that sleep while nothing in or around them changes, and
+//! streaming the grid over a world larger than it as the camera moves.
+//!
+//! Each step starts with passes over the tiles and chunks, see `chunks.wgsl`: `settle_tiles`
+//! counts the steps since something in each tile last changed, `plan_tiles` wakes the tiles that
+//! something changed in or near during the last [`SLEEP_AFTER`] steps, and `plan_chunks` lists the
+//! chunks with awake tiles in them. The step itself is then dispatched indirectly, over the listed
+//! chunks only, so that settled sand costs nothing, see [`crate::tile`].
+//!
+//! The grid is a window onto the world, at [`ChunkStreaming::origin`]. Once the camera is more
+//! than half a chunk away from the center of the grid, the grid is shifted towards it by whole
+//! chunks. The cells that stay on the grid are copied across on the GPU, as when it is resized,
+//! those that leave it are read back into a store in the main world, and those that come onto it
+//! are written from that store, or left empty where the world hasn't been visited yet.
+
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc, Mutex, OnceLock},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        ExtractSchedule, MainWorld, Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    cell::Cell,
+    input::AutomataParams,
+    material::MaterialRegistry,
+    pipeline::automata::{GameOfLifeBuffers, GameOfLifeImage},
+    readback::{create_staging_buffer, map_staging_buffer},
+    resize::{self, empty_cells, CellCopies},
+    GridConfig,
+};
+
+/// Width and height of a chunk in cells, a multiple of the workgroup size.
+///
+/// Must be kept in sync with `CHUNK_SIZE` in `core.wgsl`.
+pub const CHUNK_SIZE: u32 = 64;
+
+/// Steps that a tile and those around it go without a change before it falls asleep.
+///
+/// Must be kept in sync with `SLEEP_AFTER` in `chunks.wgsl`.
+pub const SLEEP_AFTER: u32 = 8;
+
+/// Shifts the grid over the world by whole chunks.
+#[derive(Event, Clone, Copy)]
+pub struct ShiftGrid(pub IVec2);
+
+/// Where the grid is in the world, and the cells of the world that are off of it.
+#[derive(Resource, Default)]
+pub struct ChunkStreaming {
+    /// In chunks of the world, of the top left corner of the grid.
+    origin: IVec2,
+    /// Shifts waiting for the cells that last left the grid to be stored.
+    queued: IVec2,
+    /// Number of shifts whose cells are still being read back.
+    pending: usize,
+    /// Cells that have left the grid, by chunk of the world, `None` where nothing has been yet.
+    store: HashMap<IVec2, Vec<Option<Cell>>>,
+}
+
+impl ChunkStreaming {
+    pub fn origin(&self) -> IVec2 {
+        self.origin
+    }
+
+    /// Whether a shift has been asked for but not made yet, or made but not stored yet.
+    pub fn is_shifting(&self) -> bool {
+        self.queued != IVec2::ZERO || self.pending > 0
+    }
+
+    /// The cell at `location` in the world, if it has been on the grid and left it since.
+    pub fn stored(&self, location: IVec2) -> Option<Cell> {
+        let (chunk, index) = chunk_of(location);
+        self.store.get(&chunk).and_then(|cells| cells[index])
+    }
+
+    /// Forgets every cell off the grid, so that the rest of the world is empty.
+    pub(crate) fn forget(&mut self) {
+        self.store.clear();
+    }
+
+    fn store(&mut self, location: IVec2, cell: Cell) {
+        let (chunk, index) = chunk_of(location);
+        self.store
+            .entry(chunk)
+            .or_insert_with(|| vec![None; (CHUNK_SIZE * CHUNK_SIZE) as usize])[index] = Some(cell);
+    }
+
+    /// In cells of the world, of the top left corner of the grid.
+    fn cell_origin(&self) -> IVec2 {
+        self.origin * CHUNK_SIZE as i32
+    }
+
+    /// Where the center of the grid is in the world of the camera, which has y pointing up.
+    pub fn grid_translation(&self, config: &GridConfig) -> Vec2 {
+        self.cell_origin().as_vec2() * Vec2::new(1., -1.) * config.display_factor as f32
+    }
+}
+
+// The chunk of the world that `location` is in, and the index of the cell in it
+fn chunk_of(location: IVec2) -> (IVec2, usize) {
+    let size = IVec2::splat(CHUNK_SIZE as i32);
+    let local = location.rem_euclid(size);
+    (
+        location.div_euclid(size),
+        (local.y * size.x + local.x) as usize,
+    )
+}
+
+fn contains(origin: IVec2, size: UVec2, location: IVec2) -> bool {
+    location.cmpge(origin).all() && location.cmplt(origin + size.as_ivec2()).all()
+}
+
+/// Cells of the grid before a shift, for the ones that leave it to be read back.
+struct Eviction {
+    /// Both buffers, since which one the last step wrote is only known in the render world.
+    from: Vec<Buffer>,
+    /// In cells of the world, of the top left corner of the grid before and after the shift.
+    from_origin: IVec2,
+    to_origin: IVec2,
+    size: UVec2,
+}
+
+/// Moved from the main into the render world as they are extracted, like `resize::CellCopies`.
+#[derive(Resource, Default)]
+struct Evictions(Vec<Eviction>);
+
+struct PendingEviction {
+    from_origin: IVec2,
+    to_origin: IVec2,
+    size: UVec2,
+    staging: Buffer,
+    is_mapped: Arc<OnceLock<Result<(), BufferAsyncError>>>,
+}
+
+#[derive(Resource, Default)]
+struct PendingEvictions(Vec<PendingEviction>);
+
+/// The cells of the grid before a shift, `None` if they could not be read back.
+struct EvictedCells {
+    from_origin: IVec2,
+    to_origin: IVec2,
+    size: UVec2,
+    cells: Option<Vec<Cell>>,
+}
+
+/// Shared between the main and the render world, like `readback::Readback`.
+#[derive(Resource, Clone, Default, ExtractResource)]
+struct Evicted(Arc<Mutex<Vec<EvictedCells>>>);
+
+pub struct ChunkPlugin;
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ShiftGrid>()
+            .init_resource::<ChunkStreaming>()
+            .init_resource::<Evictions>()
+            .init_resource::<Evicted>()
+            .add_plugins(ExtractResourcePlugin::<Evicted>::default())
+            .add_systems(
+                Update,
+                (store_evicted_cells, follow_camera, shift_grid)
+                    .chain()
+                    .after(resize::resize_grid),
+            );
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<Evictions>()
+            .init_resource::<PendingEvictions>()
+            .add_systems(ExtractSchedule, extract_evictions)
+            .add_systems(
+                Render,
+                (
+                    // After the cells of a resize in the same frame have been copied into the
+                    // buffers from before the shift
+                    copy_evicted_cells
+                        .in_set(RenderSet::PrepareResources)
+                        .after(resize::copy_cells),
+                    receive_evicted_cells.in_set(RenderSet::Cleanup),
+                ),
+            );
+    }
+}
+
+fn store_evicted_cells(mut streaming: ResMut<ChunkStreaming>, evicted: Res<Evicted>) {
+    for evicted in evicted.0.lock().unwrap().drain(..) {
+        streaming.pending -= 1;
+        let Some(cells) = evicted.cells else {
+            continue;
+        };
+        for (index, cell) in cells.into_iter().enumerate() {
+            let index = index as u32;
+            let location = evicted.from_origin
+                + UVec2::new(index % evicted.size.x, index / evicted.size.x).as_ivec2();
+            if !contains(evicted.to_origin, evicted.size, location) {
+                streaming.store(location, cell);
+            }
+        }
+    }
+}
+
+// Keeps the grid under the camera, by shifting it once the camera is past the middle of the
+// next chunk
+fn follow_camera(
+    cameras: Query<&Transform, With<Camera>>,
+    streaming: Res<ChunkStreaming>,
+    config: Res<GridConfig>,
+    mut shifts: EventWriter<ShiftGrid>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    if streaming.is_shifting() {
+        return;
+    }
+    // In cells, with y pointing down like the grid
+    let distance = (camera.translation.truncate() - streaming.grid_translation(&config))
+        * Vec2::new(1., -1.)
+        / config.display_factor as f32;
+    let shift = (distance / CHUNK_SIZE as f32).round().as_ivec2();
+    if shift != IVec2::ZERO {
+        shifts.send(ShiftGrid(shift));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shift_grid(
+    mut shifts: EventReader<ShiftGrid>,
+    mut streaming: ResMut<ChunkStreaming>,
+    mut buffers: ResMut<GameOfLifeBuffers>,
+    mut copies: ResMut<CellCopies>,
+    mut evictions: ResMut<Evictions>,
+    config: Res<GridConfig>,
+    device: Res<RenderDevice>,
+    registry: Option<Res<MaterialRegistry>>,
+    image: Res<GameOfLifeImage>,
+    mut sprites: Query<(&Handle<Image>, &mut Transform), With<Sprite>>,
+) {
+    for ShiftGrid(shift) in shifts.read() {
+        streaming.queued += *shift;
+    }
+    // Cells coming back onto the grid may not have been stored yet
+    if streaming.queued == IVec2::ZERO || streaming.pending > 0 {
+        return;
+    }
+    let shift = std::mem::take(&mut streaming.queued);
+    let from_origin = streaming.cell_origin();
+    streaming.origin += shift;
+    let to_origin = streaming.cell_origin();
+    // Where the top left corner of the grid before the shift ends up on the grid after it
+    let offset = from_origin - to_origin;
+
+    let mut cells = empty_cells(&config, registry.as_deref());
+    for y in 0..config.size.y {
+        for x in 0..config.size.x {
+            let location = UVec2::new(x, y).as_ivec2();
+            if contains(offset, config.size, location) {
+                continue;
+            }
+            if let Some(cell) = streaming.stored(to_origin + location) {
+                cells[(y * config.size.x + x) as usize] = cell;
+            }
+        }
+    }
+    let from_buffers = buffers.in_out.clone();
+    buffers.resize(&device, &config, &cells);
+    copies.push(
+        from_buffers.clone(),
+        buffers.in_out.clone(),
+        config.size,
+        config.size,
+        offset,
+    );
+    evictions.0.push(Eviction {
+        from: from_buffers,
+        from_origin,
+        to_origin,
+        size: config.size,
+    });
+    streaming.pending += 1;
+
+    let translation = streaming.grid_translation(&config);
+    for (handle, mut transform) in &mut sprites {
+        if *handle == image.texture {
+            transform.translation = translation.extend(transform.translation.z);
+        }
+    }
+    info!(
+        "Shifted the grid to chunk ({}, {}) of the world",
+        streaming.origin.x, streaming.origin.y
+    );
+}
+
+fn extract_evictions(mut main_world: ResMut<MainWorld>, mut evictions: ResMut<Evictions>) {
+    let mut main_evictions = main_world.resource_mut::<Evictions>();
+    evictions.0.append(&mut main_evictions.0);
+}
+
+// Before the graph runs, which no longer steps the buffers from before the shift
+fn copy_evicted_cells(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    params: Res<AutomataParams>,
+    mut evictions: ResMut<Evictions>,
+    mut pending: ResMut<PendingEvictions>,
+) {
+    if evictions.0.is_empty() {
+        return;
+    }
+    let frame = params.frame.load(Ordering::SeqCst);
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Chunk Eviction Encoder"),
+    });
+    let mut stagings = Vec::new();
+    for eviction in evictions.0.drain(..) {
+        // The buffer that the last step wrote, see `readback::copy_cells`
+        let buffer = &eviction.from[frame % 2];
+        let staging = create_staging_buffer(&render_device, buffer.size());
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+        stagings.push((eviction, staging));
+    }
+    render_queue.submit([encoder.finish()]);
+
+    for (eviction, staging) in stagings {
+        pending.0.push(PendingEviction {
+            from_origin: eviction.from_origin,
+            to_origin: eviction.to_origin,
+            size: eviction.size,
+            is_mapped: map_staging_buffer(&render_device, &staging),
+            staging,
+        });
+    }
+}
+
+fn receive_evicted_cells(mut pending: ResMut<PendingEvictions>, evicted: Res<Evicted>) {
+    pending.0.retain(|pending| {
+        let cells = match pending.is_mapped.get() {
+            None => return true,
+            Some(Ok(())) => {
+                let cells =
+                    bytemuck::pod_collect_to_vec(&pending.staging.slice(..).get_mapped_range());
+                pending.staging.unmap();
+                Some(cells)
+            }
+            Some(Err(err)) => {
+                error!("Could not read back the cells that left the grid: {err}");
+                None
+            }
+        };
+        evicted.0.lock().unwrap().push(EvictedCells {
+            from_origin: pending.from_origin,
+            to_origin: pending.to_origin,
+            size: pending.size,
+            cells,
+        });
+        false
+    });
+}
@@ -0,0 +1,97 @@
+//! Reading the [`GridConfig`] from a file, so that a size can be kept without passing it on the
+//! command line every time.
+//!
+//! The file is RON, in the format of `assets/litterbox.config.ron`, such as
+//! `(size: (256, 128), display_factor: 2)`. Anything left out keeps its default. How large a
+//! grid can be depends on the GPU, which is only known once it has been set up, see
+//! [`GridConfig::check_limits`].
+
+use std::{mem::size_of, path::Path};
+
+use bevy::{math::UVec2, render::settings::WgpuLimits};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{cell::Cell, GridConfig};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GridConfigFile {
+    size: (u32, u32),
+    display_factor: u32,
+}
+
+impl Default for GridConfigFile {
+    fn default() -> Self {
+        let config = GridConfig::default();
+        Self {
+            size: config.size.into(),
+            display_factor: config.display_factor,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GridConfigError {
+    #[error("could not read config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse config: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("grid size {0}x{1} is empty, both must be at least 1")]
+    EmptySize(u32, u32),
+    #[error("display factor must be at least 1")]
+    ZeroDisplayFactor,
+    #[error("a {0}x{1} grid is larger than the GPU can hold")]
+    TooLarge(u32, u32),
+}
+
+impl GridConfig {
+    /// Parses a config in the format of `assets/litterbox.config.ron`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GridConfigError> {
+        let file: GridConfigFile = ron::de::from_bytes(bytes)?;
+        let (width, height) = file.size;
+        if width == 0 || height == 0 {
+            return Err(GridConfigError::EmptySize(width, height));
+        }
+        if file.display_factor == 0 {
+            return Err(GridConfigError::ZeroDisplayFactor);
+        }
+        Ok(Self {
+            size: UVec2::new(width, height),
+            display_factor: file.display_factor,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GridConfigError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Checks that the buffers and the image of the grid can be created on a GPU with `limits`,
+    /// the largest buffers being those with a [`Cell`] per cell.
+    pub fn check_limits(&self, limits: &WgpuLimits) -> Result<(), GridConfigError> {
+        let cells = self.num_cells() as u64 * size_of::<Cell>() as u64;
+        if cells > max_buffer_size(limits)
+            || self.size.max_element() > limits.max_texture_dimension_2d
+        {
+            return Err(GridConfigError::TooLarge(self.size.x, self.size.y));
+        }
+        Ok(())
+    }
+
+    /// The largest grid of the same shape that passes [`Self::check_limits`], this one if it
+    /// already does.
+    pub fn fit_limits(self, limits: &WgpuLimits) -> Self {
+        let max_cells = max_buffer_size(limits) / size_of::<Cell>() as u64;
+        let scale = (max_cells as f64 / self.num_cells() as f64).sqrt().min(1.);
+        let size = (self.size.as_dvec2() * scale)
+            .floor()
+            .as_uvec2()
+            .min(UVec2::splat(limits.max_texture_dimension_2d))
+            .max(UVec2::ONE);
+        Self { size, ..self }
+    }
+}
+
+fn max_buffer_size(limits: &WgpuLimits) -> u64 {
+    u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size)
+}
@@ -0,0 +1,452 @@
+//! Harness that runs the compute shaders without a window and checks them against the CPU
+//! [`Simulation`], so that a shader edit that changes what the rules do shows up as the first
+//! cell where the two disagree.
+//!
+//! The GPU side uses the GL backend, which falls back to a software adapter (llvmpipe) on
+//! machines without a GPU. Only built with the `differential` feature, which the tests that use
+//! it require: `cargo test --features differential`.
+
+use std::{fmt, path::Path, sync::atomic::Ordering};
+
+use bevy::{
+    app::PluginsState,
+    prelude::*,
+    render::{
+        pipelined_rendering::PipelinedRenderingPlugin,
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Maintain, MapMode,
+            PipelineCache,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        settings::{Backends, RenderCreation, WgpuSettings},
+        RenderApp, RenderPlugin,
+    },
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+
+use crate::{
+    cell::Cell,
+    chunk::{ChunkStreaming, ShiftGrid, CHUNK_SIZE},
+    input::AutomataParams,
+    material::MaterialRegistry,
+    pipeline::{automata::GameOfLifeBuffers, draw::AutomataDrawPipeline},
+    reaction::ReactionTable,
+    readback::{CellsReadback, ImageReadback, RequestCells, StreamImages},
+    replay::{DrawUniform, PlayReplay, RecordReplay, Replayer, StopReplay},
+    reset::{ResetWorld, WorldResets},
+    resize::{ResizeAnchor, ResizeGrid},
+    rewind::{RewindHistory, RewindTo, StepBack},
+    simulation::Grid,
+    GameOfLifeComputePlugin, GridConfig, SimulationSeed,
+};
+
+// Loading and compiling the shaders on a software adapter can take a while
+const MAX_UPDATES: usize = 10_000;
+
+// Heat is spread with a handful of multiplications and additions per cell, which the GPU may
+// fuse or reorder
+const TEMPERATURE_TOLERANCE: f32 = 1e-3;
+const COLOR_TOLERANCE: f32 = 1e-6;
+
+/// [`GameOfLifeComputePlugin`] in a windowless app, stepped on demand.
+pub struct GpuSimulation {
+    app: App,
+}
+
+impl GpuSimulation {
+    /// Starts the app and waits for the material and reaction tables to load.
+    pub fn new() -> Self {
+        Self::with_config(GridConfig::default())
+    }
+
+    /// Same as [`Self::new`], on a grid of another size.
+    pub fn with_config(config: GridConfig) -> Self {
+        Self::with_seed(config, SimulationSeed::default())
+    }
+
+    /// Same as [`Self::with_config`], with other random choices.
+    pub fn with_seed(config: GridConfig, seed: SimulationSeed) -> Self {
+        let mut app = App::new();
+        app.insert_resource(seed);
+        app.insert_resource(config).add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(RenderPlugin {
+                    render_creation: RenderCreation::Automatic(WgpuSettings {
+                        backends: Some(Backends::GL),
+                        ..default()
+                    }),
+                    ..default()
+                })
+                .disable::<WinitPlugin>()
+                // Keeps the render world reachable between updates, see `Self::set_cells`
+                .disable::<PipelinedRenderingPlugin>(),
+            GameOfLifeComputePlugin,
+        ));
+        // Only step when asked to
+        app.world_mut().resource_mut::<AutomataParams>().is_paused = true;
+
+        while app.plugins_state() == PluginsState::Adding {
+            bevy::tasks::tick_global_task_pools_on_main_thread();
+        }
+        app.finish();
+        app.cleanup();
+
+        let mut simulation = Self { app };
+        simulation.update_until(|world| {
+            world.contains_resource::<MaterialRegistry>()
+                && world.contains_resource::<ReactionTable>()
+        });
+        simulation
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.app.world().resource::<GridConfig>().size
+    }
+
+    pub fn registry(&self) -> &MaterialRegistry {
+        self.app.world().resource::<MaterialRegistry>()
+    }
+
+    pub fn reactions(&self) -> &ReactionTable {
+        self.app.world().resource::<ReactionTable>()
+    }
+
+    pub fn seed(&self) -> SimulationSeed {
+        self.app.world().resource::<AutomataParams>().seed
+    }
+
+    /// Number of steps taken so far.
+    pub fn frame(&self) -> u32 {
+        let params = self.app.world().resource::<AutomataParams>();
+        params.frame.load(Ordering::SeqCst) as u32
+    }
+
+    /// Runs `steps` steps of `GameOfLifeNode`. The grid is initialized by the `init` entry point
+    /// before the first one.
+    pub fn step(&mut self, steps: u32) {
+        let params = self.app.world().resource::<AutomataParams>();
+        let frame = params.frame.load(Ordering::SeqCst) + steps as usize;
+        params
+            .steps_left
+            .fetch_add(steps as usize, Ordering::SeqCst);
+        self.update_until(|world| {
+            let params = world.resource::<AutomataParams>();
+            params.frame.load(Ordering::SeqCst) == frame
+                && params.steps_left.load(Ordering::SeqCst) == 0
+        });
+    }
+
+    /// Runs `steps` steps like [`Self::step`] while streaming the image, as a recording does, and
+    /// returns the steps that it was read back after, see [`crate::readback`].
+    pub fn step_streamed(&mut self, steps: u32) -> Vec<usize> {
+        self.app.world_mut().send_event(StreamImages(true));
+        let mut reader = self
+            .app
+            .world()
+            .resource::<Events<ImageReadback>>()
+            .get_reader_current();
+        let params = self.app.world().resource::<AutomataParams>();
+        let frame = params.frame.load(Ordering::SeqCst) + steps as usize;
+        params
+            .steps_left
+            .fetch_add(steps as usize, Ordering::SeqCst);
+        let mut frames = Vec::new();
+        self.update_until(|world| {
+            let readbacks = world.resource::<Events<ImageReadback>>();
+            frames.extend(reader.read(readbacks).map(|readback| readback.frame));
+            frames.last() == Some(&frame)
+        });
+        self.app.world_mut().send_event(StreamImages(false));
+        self.app.update();
+        frames
+    }
+
+    /// Replaces the cells that the next step reads from. Only call this after [`Self::step`],
+    /// before that the `init` entry point may still overwrite them.
+    pub fn set_cells(&mut self, cells: &[Cell]) {
+        let render_world = self.app.sub_app(RenderApp).world();
+        let buffers = render_world.resource::<GameOfLifeBuffers>();
+        let buffer = &buffers.in_out[self.frame() as usize % 2];
+        let render_queue = render_world.resource::<RenderQueue>();
+        render_queue.write_buffer(buffer, 0, bytemuck::cast_slice(cells));
+        buffers.wake(render_queue);
+    }
+
+    /// Paints a brush stroke before the next step, once the draw pipeline has loaded.
+    pub fn paint(&mut self, stroke: DrawUniform) {
+        self.update_until_rendered(|render_world| {
+            let pipeline_cache = render_world.resource::<PipelineCache>();
+            render_world
+                .resource::<AutomataDrawPipeline>()
+                .is_ready(pipeline_cache)
+        });
+        let mut params = self.app.world_mut().resource_mut::<AutomataParams>();
+        params.is_drawing = true;
+        params.prev_mouse_pos = Vec2::from_array(stroke.start);
+        params.mouse_pos = Vec2::from_array(stroke.end);
+        params.brush_radius = stroke.radius;
+        params.brush_type_id = stroke.type_id;
+        self.app.update();
+        self.app
+            .world_mut()
+            .resource_mut::<AutomataParams>()
+            .is_drawing = false;
+    }
+
+    /// Starts recording a replay to `path`, and waits for the cells that it starts from, see
+    /// [`crate::replay`].
+    pub fn record_replay(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref().to_owned();
+        self.app.world_mut().send_event(RecordReplay(path));
+        self.update_until(|world| world.resource::<Replayer>().has_started());
+    }
+
+    /// Stops recording the replay and saves it.
+    pub fn stop_replay(&mut self) {
+        self.app.world_mut().send_event(StopReplay);
+        self.update_until(|world| !world.resource::<Replayer>().is_recording());
+    }
+
+    /// Replaces the cells with those that the replay at `path` starts from, and plays back its
+    /// inputs as the steps they were recorded at come up.
+    pub fn play_replay(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref().to_owned();
+        self.app.world_mut().send_event(PlayReplay(path));
+        self.app.update();
+    }
+
+    /// Whether the replay being played has inputs left.
+    pub fn is_replaying(&self) -> bool {
+        self.app.world().resource::<Replayer>().is_playing()
+    }
+
+    /// Goes back to an earlier step that is still kept, see [`crate::rewind`].
+    pub fn rewind(&mut self, frame: u32) {
+        self.app.world_mut().send_event(RewindTo(frame as usize));
+        self.app.update();
+    }
+
+    /// Goes back to the last step kept before the current one.
+    pub fn step_back(&mut self) {
+        self.app.world_mut().send_event(StepBack);
+        self.app.update();
+    }
+
+    /// The steps that can be gone back to, oldest first.
+    pub fn rewind_steps(&self) -> Vec<u32> {
+        let history = self.app.world().resource::<RewindHistory>();
+        history
+            .steps()
+            .into_iter()
+            .map(|step| step as u32)
+            .collect()
+    }
+
+    /// Fills the grid anew and starts over from step 0, see [`crate::reset`]. An image is
+    /// imported once its palette has loaded, which may take more updates.
+    pub fn reset(&mut self, reset: ResetWorld) {
+        let count = self.app.world().resource::<WorldResets>().count;
+        self.app.world_mut().send_event(reset);
+        self.update_until(|world| world.resource::<WorldResets>().count > count);
+    }
+
+    /// Resizes the grid before the next step, see [`crate::resize`]. A size that the GPU can't
+    /// hold is turned down, leaving the grid as it was.
+    pub fn resize(&mut self, size: UVec2, anchor: ResizeAnchor) {
+        self.app.world_mut().send_event(ResizeGrid { size, anchor });
+        self.app.update();
+    }
+
+    /// Shifts the grid over the world by `chunks`, and waits for the cells that leave it to be
+    /// stored, see [`crate::chunk`]. The camera is moved along, so that the grid isn't shifted
+    /// back under it.
+    pub fn shift(&mut self, chunks: IVec2) {
+        let origin = self.app.world().resource::<ChunkStreaming>().origin() + chunks;
+        let display_factor = self.app.world().resource::<GridConfig>().display_factor;
+        let world = self.app.world_mut();
+        let mut cameras = world.query_filtered::<&mut Transform, With<Camera>>();
+        for mut camera in cameras.iter_mut(world) {
+            // y points up for the camera and down for the grid
+            let distance = (chunks * CHUNK_SIZE as i32 * IVec2::new(1, -1)).as_vec2();
+            camera.translation += (distance * display_factor as f32).extend(0.);
+        }
+        world.send_event(ShiftGrid(chunks));
+        self.update_until(|world| {
+            let streaming = world.resource::<ChunkStreaming>();
+            streaming.origin() == origin && !streaming.is_shifting()
+        });
+    }
+
+    /// Number of chunks that the last step was dispatched over, see [`crate::chunk`].
+    pub fn awake_chunks(&mut self) -> u32 {
+        let [_, _, awake]: [u32; 3] = self.read_buffer(|buffers| &buffers.dispatch);
+        awake
+    }
+
+    // Waits for a copy of one of the small buffers that the shaders keep their counts in
+    fn read_buffer<T: bytemuck::Pod>(&self, buffer: impl Fn(&GameOfLifeBuffers) -> &Buffer) -> T {
+        let render_world = self.app.sub_app(RenderApp).world();
+        let render_device = render_world.resource::<RenderDevice>();
+        let buffer = buffer(render_world.resource::<GameOfLifeBuffers>());
+        let staging = render_device.create_buffer(&BufferDescriptor {
+            label: Some("Counts Staging Buffer"),
+            size: buffer.size(),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Counts Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+        render_world
+            .resource::<RenderQueue>()
+            .submit([encoder.finish()]);
+
+        render_device.map_buffer(&staging.slice(..), MapMode::Read, |_| {});
+        render_device.poll(Maintain::Wait);
+        let value = bytemuck::pod_read_unaligned(&staging.slice(..).get_mapped_range());
+        value
+    }
+
+    /// Number of tiles that the last step went over, see [`crate::tile`].
+    pub fn awake_tiles(&mut self) -> u32 {
+        let [awake, _]: [u32; 2] = self.read_buffer(|buffers| &buffers.tile_stats);
+        awake
+    }
+
+    /// Reads back the cells that the last step wrote, see [`crate::readback`].
+    pub fn cells(&mut self) -> Vec<Cell> {
+        self.app.world_mut().send_event(RequestCells);
+        let mut reader = self
+            .app
+            .world()
+            .resource::<Events<CellsReadback>>()
+            .get_reader_current();
+        let mut cells = None;
+        self.update_until(|world| {
+            let readbacks = world.resource::<Events<CellsReadback>>();
+            cells = reader
+                .read(readbacks)
+                .last()
+                .map(|readback| readback.cells.clone());
+            cells.is_some()
+        });
+        cells.unwrap()
+    }
+
+    fn update_until_rendered(&mut self, is_done: impl Fn(&World) -> bool) {
+        for _ in 0..MAX_UPDATES {
+            if is_done(self.app.sub_app(RenderApp).world()) {
+                return;
+            }
+            self.app.update();
+        }
+        panic!("the GPU simulation got stuck at step {}", self.frame());
+    }
+
+    fn update_until(&mut self, mut is_done: impl FnMut(&World) -> bool) {
+        for _ in 0..MAX_UPDATES {
+            self.app.update();
+            if is_done(self.app.world()) {
+                return;
+            }
+        }
+        panic!("the GPU simulation got stuck at step {}", self.frame());
+    }
+}
+
+impl Default for GpuSimulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The first cell, row by row from the top, where the GPU disagrees with the CPU.
+#[derive(Debug)]
+pub struct Divergence {
+    pub frame: u32,
+    pub location: IVec2,
+    pub expected: Cell,
+    pub actual: Cell,
+    /// Number of cells that disagree.
+    pub count: usize,
+    expected_name: String,
+    actual_name: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "after {} steps, {} cells differ, the first one at ({}, {}):",
+            self.frame, self.count, self.location.x, self.location.y
+        )?;
+        for (side, name, cell) in [
+            ("cpu", &self.expected_name, &self.expected),
+            ("gpu", &self.actual_name, &self.actual),
+        ] {
+            writeln!(
+                f,
+                "  {side}: {name} (type_id {}), lifetime {}, {}°C, color {:?}",
+                cell.type_id, cell.lifetime, cell.temperature, cell.color
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Compares the cells read back from the GPU with those of the CPU [`Grid`] after `frame` steps.
+pub fn compare(
+    frame: u32,
+    expected: &Grid,
+    actual: &[Cell],
+    registry: &MaterialRegistry,
+) -> Result<(), Box<Divergence>> {
+    assert_eq!(
+        expected.cells().len(),
+        actual.len(),
+        "the grids have different sizes"
+    );
+    let mut differences = expected
+        .cells()
+        .iter()
+        .zip(actual)
+        .enumerate()
+        .filter(|(_, (expected, actual))| !matches(expected, actual));
+    let Some((index, (&expected_cell, &actual_cell))) = differences.next() else {
+        return Ok(());
+    };
+    let name = |cell: &Cell| registry.name(cell.type_id).unwrap_or("?").to_owned();
+    Err(Box::new(Divergence {
+        frame,
+        location: IVec2::new(
+            (index % expected.width() as usize) as i32,
+            (index / expected.width() as usize) as i32,
+        ),
+        expected: expected_cell,
+        actual: actual_cell,
+        count: 1 + differences.count(),
+        expected_name: name(&expected_cell),
+        actual_name: name(&actual_cell),
+    }))
+}
+
+fn matches(expected: &Cell, actual: &Cell) -> bool {
+    expected.type_id == actual.type_id
+        && expected.lifetime == actual.lifetime
+        && (expected.temperature - actual.temperature).abs()
+            <= TEMPERATURE_TOLERANCE * expected.temperature.abs().max(1.)
+        && expected
+            .color
+            .iter()
+            .zip(actual.color)
+            .all(|(expected, actual)| (expected - actual).abs() <= COLOR_TOLERANCE)
+}
@@ -0,0 +1,248 @@
+//! Importing levels drawn in an image editor.
+//!
+//! Each pixel of the image turns into a cell of the material its color stands for in the
+//! [`Palette`], `assets/litterbox.palette.ron`. The cells then replace the whole grid like a
+//! loaded snapshot does, see [`crate::snapshot`].
+
+use std::{path::PathBuf, sync::atomic::Ordering};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use image::{imageops, RgbaImage};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    cell::Cell,
+    input::AutomataParams,
+    material::MaterialRegistry,
+    simulation::{hash, random_float, spawn_cell},
+    snapshot::{self, Snapshot},
+    GridConfig, SimulationSeed,
+};
+
+const PALETTE_ASSET_PATH: &str = "litterbox.palette.ron";
+
+/// How pixels are matched against the colors of a [`Palette`].
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum ColorMatching {
+    /// Pixels of a color that isn't in the palette are left empty.
+    Exact,
+    /// Pixels take the material of the closest color in the palette.
+    #[default]
+    Nearest,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaletteColor {
+    /// Red, green and blue, from 0 to 255.
+    pub color: [u8; 3],
+    pub material: String,
+}
+
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct Palette {
+    #[serde(default)]
+    pub matching: ColorMatching,
+    pub colors: Vec<PaletteColor>,
+}
+
+impl Palette {
+    /// Parses a palette in the format of `assets/litterbox.palette.ron`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImportError> {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("could not read palette: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse palette: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("could not read image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("palette refers to `{0}`, which is not in the material table")]
+    UnknownMaterial(String),
+}
+
+#[derive(Default)]
+pub struct PaletteLoader;
+
+impl AssetLoader for PaletteLoader {
+    type Asset = Palette;
+    type Settings = ();
+    type Error = ImportError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Palette, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Palette::from_bytes(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["palette.ron"]
+    }
+}
+
+/// How an image that isn't the size of the grid is made to fit it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFit {
+    /// Scaled to the size of the grid, without blending colors.
+    #[default]
+    Resize,
+    /// Centered on the grid at its own size, cut off where it is larger and surrounded by empty
+    /// cells where it is smaller.
+    Crop,
+}
+
+/// Replaces every cell with those drawn in the image at `path`.
+#[derive(Event, Clone)]
+pub struct ImportImage {
+    pub path: PathBuf,
+    pub fit: ImageFit,
+}
+
+/// Turns each pixel of `image`, once made to fit a `size` grid, into a cell of the material
+/// that `palette` maps its color to, varied by `seed` like the cells that `init` places.
+pub fn cells_from_image(
+    image: &RgbaImage,
+    size: UVec2,
+    fit: ImageFit,
+    palette: &Palette,
+    registry: &MaterialRegistry,
+    seed: SimulationSeed,
+) -> Result<Vec<Cell>, ImportError> {
+    let colors = palette
+        .colors
+        .iter()
+        .map(|entry| {
+            let type_id = registry
+                .id(&entry.material)
+                .ok_or_else(|| ImportError::UnknownMaterial(entry.material.clone()))?;
+            Ok((entry.color, type_id))
+        })
+        .collect::<Result<Vec<_>, ImportError>>()?;
+
+    let image = match fit {
+        ImageFit::Resize => imageops::resize(image, size.x, size.y, imageops::FilterType::Nearest),
+        ImageFit::Crop => {
+            let mut canvas = RgbaImage::new(size.x, size.y);
+            let x = (i64::from(size.x) - i64::from(image.width())) / 2;
+            let y = (i64::from(size.y) - i64::from(image.height())) / 2;
+            imageops::replace(&mut canvas, image, x, y);
+            canvas
+        }
+    };
+
+    let materials = registry.to_gpu();
+    let cells = image
+        .pixels()
+        .enumerate()
+        .map(|(index, pixel)| {
+            let [r, g, b, a] = pixel.0;
+            let type_id = if a < 128 {
+                0
+            } else {
+                match_color([r, g, b], &colors, palette.matching)
+            };
+            // Same randomness as the `init` entry point
+            spawn_cell(
+                type_id,
+                &materials[type_id as usize],
+                random_float(index as u32 ^ hash(seed.0)),
+            )
+        })
+        .collect();
+    Ok(cells)
+}
+
+fn match_color(color: [u8; 3], colors: &[([u8; 3], i32)], matching: ColorMatching) -> i32 {
+    let distance = |other: &[u8; 3]| -> u32 {
+        color
+            .iter()
+            .zip(other)
+            .map(|(&a, &b)| u32::from(a.abs_diff(b)).pow(2))
+            .sum()
+    };
+    match matching {
+        ColorMatching::Exact => colors.iter().find(|(other, _)| *other == color),
+        ColorMatching::Nearest => colors.iter().min_by_key(|(other, _)| distance(other)),
+    }
+    .map_or(0, |&(_, type_id)| type_id)
+}
+
+/// Reads the image of `import` into a snapshot of a `size` grid that goes on from step `frame`
+/// with `seed`.
+pub(crate) fn snapshot_from_image(
+    import: &ImportImage,
+    size: UVec2,
+    frame: u64,
+    seed: SimulationSeed,
+    palette: &Palette,
+    registry: &MaterialRegistry,
+) -> Result<Snapshot, ImportError> {
+    let image = image::open(&import.path)?.into_rgba8();
+    let cells = cells_from_image(&image, size, import.fit, palette, registry, seed)?;
+    Ok(Snapshot {
+        frame,
+        seed,
+        size,
+        materials: registry.fingerprint(),
+        cells,
+    })
+}
+
+#[derive(Resource)]
+pub(crate) struct PaletteHandle(pub(crate) Handle<Palette>);
+
+pub struct ImportPlugin;
+impl Plugin for ImportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Palette>()
+            .init_asset_loader::<PaletteLoader>()
+            .add_event::<ImportImage>()
+            .add_systems(Startup, load_palette)
+            .add_systems(Update, import_images);
+    }
+}
+
+fn load_palette(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(PaletteHandle(asset_server.load(PALETTE_ASSET_PATH)));
+}
+
+// Imports wait for the palette and the material table, so that a level can be imported from the
+// first frame on
+#[allow(clippy::too_many_arguments)]
+fn import_images(
+    mut commands: Commands,
+    mut imports: EventReader<ImportImage>,
+    mut pending: Local<Vec<ImportImage>>,
+    handle: Res<PaletteHandle>,
+    palettes: Res<Assets<Palette>>,
+    mut params: ResMut<AutomataParams>,
+    config: Res<GridConfig>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    pending.extend(imports.read().cloned());
+    let (Some(palette), Some(registry)) = (palettes.get(&handle.0), registry) else {
+        return;
+    };
+    for import in pending.drain(..) {
+        let frame = params.frame.load(Ordering::SeqCst) as u64;
+        match snapshot_from_image(&import, config.size, frame, params.seed, palette, &registry) {
+            Ok(snapshot) => {
+                info!("Imported {}", import.path.display());
+                snapshot::upload(&mut commands, &mut params, snapshot);
+            }
+            Err(err) => error!("Could not import {}: {err}", import.path.display()),
+        }
+    }
+}
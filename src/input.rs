@@ -3,16 +3,60 @@ use std::sync::{
     Arc,
 };
 
-use bevy::{prelude::*, render::extract_resource::ExtractResource};
-use std::time::Duration;
+use bevy::{input::mouse::MouseWheel, prelude::*, render::extract_resource::ExtractResource};
 
-const FRAMES_PER_SECOND: i32 = 2;
+use crate::{
+    chunk::ChunkStreaming,
+    import::ImportImage,
+    material::MaterialRegistry,
+    recording::{Recorder, RecordingFormat, StartRecording, StopRecording},
+    replay::{PlayReplay, RecordReplay, ReplayInput, Replayer, StopReplay},
+    reset::ResetWorld,
+    resize::ResizeGrid,
+    rewind::StepBack,
+    screenshot::TakeScreenshot,
+    snapshot::{LoadSnapshot, SaveSnapshot, QUICKSAVE_PATH},
+    speed::SimulationSpeed,
+    GridConfig, SimulationSeed,
+};
+
+// Logical pixels per second that the camera moves over the world
+const PAN_SPEED: f32 = 400.;
+
+// Cells added to or cropped from each side of the grid at a time
+const RESIZE_STEP: u32 = 16;
+
+const MIN_BRUSH_RADIUS: f32 = 0.5;
+const MAX_BRUSH_RADIUS: f32 = 32.;
+
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
 
 #[derive(Debug, Resource, Clone, ExtractResource)]
 pub struct AutomataParams {
     pub is_paused: bool,
     pub frame: Arc<AtomicUsize>,
     pub steps_left: Arc<AtomicUsize>,
+    pub is_drawing: bool,
+    // Brush position in canvas (cell) coordinates, for this and the previous frame
+    pub mouse_pos: Vec2,
+    pub prev_mouse_pos: Vec2,
+    pub brush_radius: f32,
+    pub brush_type_id: i32,
+    // Show the temperature field instead of the materials
+    pub show_heatmap: bool,
+    // Seeds the random choices of `init` and of every step
+    pub seed: SimulationSeed,
 }
 
 impl Default for AutomataParams {
@@ -21,6 +65,14 @@ impl Default for AutomataParams {
             is_paused: false,
             frame: Arc::new(AtomicUsize::new(0)),
             steps_left: Arc::new(AtomicUsize::new(0)),
+            is_drawing: false,
+            mouse_pos: Vec2::ZERO,
+            prev_mouse_pos: Vec2::ZERO,
+            brush_radius: 2.,
+            // First material after the empty one
+            brush_type_id: 1,
+            show_heatmap: false,
+            seed: SimulationSeed::default(),
         }
     }
 }
@@ -28,94 +80,316 @@ impl Default for AutomataParams {
 pub struct InputPlugin;
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<AutomataParams>()
-            .add_systems(Startup, setup_draw_timer)
-            .add_systems(Update, update_input_state)
-            .add_systems(FixedUpdate, update_ready);
+        let seed = app
+            .world()
+            .get_resource::<SimulationSeed>()
+            .copied()
+            .unwrap_or_default();
+        // To repeat the run with `--seed`
+        info!("Seed {}", seed.0);
+        app.insert_resource(AutomataParams { seed, ..default() })
+            .add_systems(
+                Update,
+                (
+                    update_input_state,
+                    select_brush_material,
+                    change_speed,
+                    step_back,
+                    reset_world,
+                    save_or_load_snapshot,
+                    take_screenshot,
+                    toggle_recording,
+                    toggle_replay_recording,
+                    resize_grid,
+                    pan_camera,
+                    open_dropped_files,
+                ),
+            );
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update_input_state(
-    // mut contexts: EguiContexts,
-    // window_query: Query<&Window>,
+    window_query: Query<&Window>,
     mut params: ResMut<AutomataParams>,
+    config: Res<GridConfig>,
+    streaming: Res<ChunkStreaming>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    // camera_q: Query<(&Camera, &GlobalTransform)>,
-    // mut mouse_button_input_events: EventReader<MouseButtonInput>,
+    mouse_button_input: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    ui: Query<&Interaction>,
+    mut inputs: EventWriter<ReplayInput>,
 ) {
-    // let Ok(primary_window) = window_query.get_single() else {
-    //     return;
-    // };
-    // // get the camera info and transform
-    // let Ok((camera, camera_transform)) = camera_q.get_single() else {
-    //     return;
-    // };
-
-    // let ctx = contexts.ctx_mut();
-    // if ctx.wants_pointer_input()
-    //     || ctx.is_pointer_over_area()
-    //     || ctx.is_using_pointer()
-    //     || ctx.wants_pointer_input()
-    // {
-    //     // GUI gets priority input
-    //     params.is_drawing = false;
-    //     params.can_scroll = false;
-    //     return;
-    // } else {
-    //     params.can_scroll = true;
-    // }
-
-    // Determine button state
-    // for event in mouse_button_input_events.iter() {
-    //     if event.button == MouseButton::Left {
-    //         params.is_drawing = event.state == ButtonState::Pressed;
-    //     }
-    // }
+    let Ok(primary_window) = window_query.get_single() else {
+        return;
+    };
+    // get the camera info and transform
+    let Ok((camera, camera_transform)) = camera_q.get_single() else {
+        return;
+    };
 
     // Pause the simulation
     if keyboard_input.just_pressed(KeyCode::Space) {
         params.is_paused = !params.is_paused;
+        inputs.send(ReplayInput::Pause(params.is_paused));
     }
 
     if keyboard_input.just_pressed(KeyCode::KeyF) {
-        params.steps_left.store(1, Ordering::SeqCst);
-    }
-
-    // if let Some(world_position) = primary_window
-    //     .cursor_position()
-    //     .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-    //     .map(|ray| ray.origin.truncate())
-    // {
-    //     params.prev_mouse_pos = params.mouse_pos;
-    //     params.mouse_pos =
-    //         crate::utils::world_pos_to_canvas_pos(world_position * Vec2::new(1.0, -1.0));
-    // }
+        params.steps_left.fetch_add(1, Ordering::SeqCst);
+        inputs.send(ReplayInput::Step);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::KeyH) {
+        params.show_heatmap = !params.show_heatmap;
+        inputs.send(ReplayInput::Heatmap(params.show_heatmap));
+    }
+
+    // Carry on with different random choices from here
+    if keyboard_input.just_pressed(KeyCode::KeyS) {
+        params.seed = SimulationSeed::random();
+        info!("Seed {}", params.seed.0);
+    }
+
+    // Resize the brush
+    for event in mouse_wheel_events.read() {
+        params.brush_radius =
+            (params.brush_radius + event.y.signum()).clamp(MIN_BRUSH_RADIUS, MAX_BRUSH_RADIUS);
+        inputs.send(ReplayInput::BrushRadius(params.brush_radius));
+    }
+
+    // A touch is treated the same as holding the left mouse button, except on the UI
+    let touch_position = touches.iter().next().map(|touch| touch.position());
+    let was_drawing = params.is_drawing;
+    let is_over_ui = ui
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+    params.is_drawing =
+        (mouse_button_input.pressed(MouseButton::Left) || touch_position.is_some()) && !is_over_ui;
+
+    if let Some(world_position) = touch_position
+        .or_else(|| primary_window.cursor_position())
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .map(|ray| ray.origin.truncate())
+    {
+        params.prev_mouse_pos = params.mouse_pos;
+        // Relative to the grid, which moves over the world with the camera
+        let world_position = world_position - streaming.grid_translation(&config);
+        params.mouse_pos =
+            crate::utils::world_pos_to_canvas_pos(world_position * Vec2::new(1.0, -1.0), &config);
+    }
+
+    // Don't connect a new stroke to wherever the previous one ended
+    if params.is_drawing && !was_drawing {
+        params.prev_mouse_pos = params.mouse_pos;
+    }
 }
 
-#[derive(Resource)]
-pub struct DrawTimer {
-    timer: Timer,
+// Select the material to paint with by its position in the material table,
+// 0 being the empty material (eraser)
+pub fn select_brush_material(
+    mut params: ResMut<AutomataParams>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    registry: Option<Res<MaterialRegistry>>,
+    mut inputs: EventWriter<ReplayInput>,
+) {
+    let Some(registry) = registry else {
+        return;
+    };
+    let previous = params.brush_type_id;
+    for (type_id, key) in DIGIT_KEYS.into_iter().enumerate() {
+        if keyboard_input.just_pressed(key) && type_id < registry.len() {
+            params.brush_type_id = type_id as i32;
+        }
+    }
+
+    // Brackets cycle through all materials, including those past the digit keys
+    let len = registry.len() as i32;
+    if keyboard_input.just_pressed(KeyCode::BracketRight) {
+        params.brush_type_id = (params.brush_type_id + 1).rem_euclid(len);
+    }
+    if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+        params.brush_type_id = (params.brush_type_id - 1).rem_euclid(len);
+    }
+    if params.brush_type_id != previous {
+        inputs.send(ReplayInput::Material(params.brush_type_id));
+    }
 }
 
-pub fn update_ready(mut timer: ResMut<DrawTimer>, params: ResMut<AutomataParams>, time: Res<Time>) {
-    if params.is_paused {
+// Period doubles the speed, comma halves it, see `speed`
+pub fn change_speed(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut inputs: EventWriter<ReplayInput>,
+) {
+    let changed = if keyboard_input.just_pressed(KeyCode::Period) {
+        speed.faster()
+    } else if keyboard_input.just_pressed(KeyCode::Comma) {
+        speed.slower()
+    } else {
         return;
+    };
+    if changed != *speed {
+        *speed = changed;
+        inputs.send(ReplayInput::Speed(speed.steps_per_second));
     }
-    timer.timer.tick(time.delta());
-    if timer.timer.just_finished() {
-        // eprintln!(
-        //     "Frame {} Steps left {}",
-        //     params.frame.load(Ordering::SeqCst),
-        //     params.steps_left.load(Ordering::SeqCst)
-        // );
-        params.steps_left.store(1, Ordering::SeqCst);
+}
+
+// Backspace goes back a step, see `rewind`
+pub fn step_back(keyboard_input: Res<ButtonInput<KeyCode>>, mut step_backs: EventWriter<StepBack>) {
+    if keyboard_input.just_pressed(KeyCode::Backspace) {
+        step_backs.send(StepBack);
     }
 }
 
-fn setup_draw_timer(mut commands: Commands) {
-    let x = 1.0 / (FRAMES_PER_SECOND) as f32;
-    eprintln!("{}", x);
-    commands.insert_resource(DrawTimer {
-        timer: Timer::new(Duration::from_secs_f32(x), TimerMode::Repeating),
-    })
+// N starts over with a new seed, Shift+N with the same one, see `reset`
+pub fn reset_world(keyboard_input: Res<ButtonInput<KeyCode>>, mut resets: EventWriter<ResetWorld>) {
+    if !keyboard_input.just_pressed(KeyCode::KeyN) {
+        return;
+    }
+    let is_same_seed = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    resets.send(ResetWorld {
+        seed: (!is_same_seed).then(SimulationSeed::random),
+        initializer: None,
+    });
+}
+
+pub fn save_or_load_snapshot(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut saves: EventWriter<SaveSnapshot>,
+    mut loads: EventWriter<LoadSnapshot>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        saves.send(SaveSnapshot(QUICKSAVE_PATH.into()));
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        loads.send(LoadSnapshot(QUICKSAVE_PATH.into()));
+    }
+}
+
+// P saves the grid at its own resolution, Shift+P at the size it has in the window
+pub fn take_screenshot(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut screenshots: EventWriter<TakeScreenshot>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyP) {
+        screenshots.send(TakeScreenshot {
+            upscale: keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+        });
+    }
+}
+
+// R records an APNG, Shift+R a PNG sequence, and either stops the running recording
+pub fn toggle_recording(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    recorder: Res<Recorder>,
+    mut starts: EventWriter<StartRecording>,
+    mut stops: EventWriter<StopRecording>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyR) {
+        return;
+    }
+    if recorder.is_recording() {
+        stops.send(StopRecording);
+    } else if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        starts.send(StartRecording {
+            format: RecordingFormat::PngSequence,
+            ..default()
+        });
+    } else {
+        starts.send(StartRecording::default());
+    }
+}
+
+// F6 starts recording a replay, and saves it when pressed again
+pub fn toggle_replay_recording(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    replayer: Res<Replayer>,
+    mut starts: EventWriter<RecordReplay>,
+    mut stops: EventWriter<StopReplay>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F6) {
+        return;
+    }
+    if replayer.is_recording() {
+        stops.send(StopReplay);
+    } else {
+        let timestamp = crate::utils::timestamp();
+        starts.send(RecordReplay(format!("litterbox-{timestamp}.replay").into()));
+    }
+}
+
+// = grows the grid around its center, - shrinks it
+pub fn resize_grid(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    config: Res<GridConfig>,
+    mut resizes: EventWriter<ResizeGrid>,
+) {
+    let size = if keyboard_input.just_pressed(KeyCode::Equal) {
+        config.size + 2 * RESIZE_STEP
+    } else if keyboard_input.just_pressed(KeyCode::Minus) {
+        config
+            .size
+            .saturating_sub(UVec2::splat(2 * RESIZE_STEP))
+            .max(UVec2::ONE)
+    } else {
+        return;
+    };
+    resizes.send(ResizeGrid {
+        size,
+        anchor: default(),
+    });
+}
+
+// Arrow keys move the camera, and the grid follows it over the world, see `chunk`
+pub fn pan_camera(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+) {
+    let axis = |negative, positive| {
+        f32::from(keyboard_input.pressed(positive)) - f32::from(keyboard_input.pressed(negative))
+    };
+    let direction = Vec2::new(
+        axis(KeyCode::ArrowLeft, KeyCode::ArrowRight),
+        axis(KeyCode::ArrowDown, KeyCode::ArrowUp),
+    );
+    if direction == Vec2::ZERO {
+        return;
+    }
+    for mut transform in &mut cameras {
+        transform.translation += (direction * PAN_SPEED * time.delta_seconds()).extend(0.);
+    }
+}
+
+// Dropping a level image, a snapshot or a replay onto the window replaces the grid with it
+pub fn open_dropped_files(
+    mut drops: EventReader<FileDragAndDrop>,
+    mut imports: EventWriter<ImportImage>,
+    mut loads: EventWriter<LoadSnapshot>,
+    mut plays: EventWriter<PlayReplay>,
+) {
+    for drop in drops.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = drop else {
+            continue;
+        };
+        match path_buf
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("litter") => {
+                loads.send(LoadSnapshot(path_buf.clone()));
+            }
+            Some("replay") => {
+                plays.send(PlayReplay(path_buf.clone()));
+            }
+            _ => {
+                imports.send(ImportImage {
+                    path: path_buf.clone(),
+                    fit: default(),
+                });
+            }
+        }
+    }
 }
@@ -1,108 +1,244 @@
-mod cell;
+pub mod cell;
+pub mod chunk;
+pub mod config;
+#[cfg(feature = "differential")]
+pub mod differential;
+pub mod import;
 mod input;
+pub mod material;
 mod pipeline;
+pub mod reaction;
+pub mod readback;
+pub mod recording;
+pub mod replay;
+pub mod reset;
+pub mod resize;
+pub mod rewind;
+pub mod screenshot;
+pub mod simulation;
+pub mod snapshot;
+pub mod speed;
+pub mod tile;
 mod utils;
 
 use bevy::{
     core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping},
     prelude::*,
     render::{
-        extract_resource::ExtractResourcePlugin, render_asset::RenderAssetUsages,
-        render_graph::RenderGraph, render_resource::*, renderer::RenderDevice, RenderApp,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssetUsages,
+        render_graph::RenderGraph,
+        render_resource::*,
+        renderer::RenderDevice,
+        RenderApp,
     },
 };
 use input::AutomataParams;
 use iyes_perf_ui::entries::PerfUiBundle;
 
-use cell::Cell;
+use material::MaterialRegistry;
 use pipeline::{
     automata::{self, GameOfLifeBuffers, GameOfLifeImage, GameOfLifeLabel, GameOfLifeNode},
     color::{self, AutomataColorLabel, AutomataColorNode},
+    draw::{self, AutomataDrawLabel, AutomataDrawNode},
 };
+use reaction::ReactionTable;
+use tile::PerfUiEntryActiveTiles;
 
+// Must be kept in sync with `TILE_SIZE` in `core.wgsl`
 const WORKGROUP_SIZE: u32 = 8;
 
-pub const DISPLAY_FACTOR: u32 = 4;
-// Should be a multiple of WORKGROUP_SIZE
-pub const SIZE: (u32, u32) = (64 * 8 / DISPLAY_FACTOR, 64 * 8 / DISPLAY_FACTOR);
-const NUM_OF_CELLS: usize = (SIZE.0 * SIZE.1) as usize;
+/// Size of the grid, and of the sprite it is shown on. Insert it before adding
+/// [`GameOfLifeComputePlugin`] to change it from the default.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, ExtractResource)]
+pub struct GridConfig {
+    /// Width and height in cells. Need not be a multiple of the workgroup size.
+    pub size: UVec2,
+    /// How many pixels wide a cell is shown in the window.
+    pub display_factor: u32,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            size: UVec2::splat(128),
+            display_factor: 4,
+        }
+    }
+}
+
+impl GridConfig {
+    pub fn num_cells(&self) -> usize {
+        self.size.x as usize * self.size.y as usize
+    }
+
+    /// Workgroups to dispatch to cover every cell, the shaders skip the ones past the edges.
+    pub fn workgroups(&self) -> UVec2 {
+        UVec2::new(
+            self.size.x.div_ceil(WORKGROUP_SIZE),
+            self.size.y.div_ceil(WORKGROUP_SIZE),
+        )
+    }
+
+    /// Tiles of `WORKGROUP_SIZE` cells that activity is tracked for, one workgroup each, see
+    /// [`tile`].
+    pub fn tiles(&self) -> UVec2 {
+        self.workgroups()
+    }
+
+    pub fn num_tiles(&self) -> usize {
+        let tiles = self.tiles();
+        tiles.x as usize * tiles.y as usize
+    }
+
+    /// Workgroups to dispatch to cover every tile, with an invocation per tile.
+    pub fn tile_workgroups(&self) -> UVec2 {
+        let tiles = self.tiles();
+        UVec2::new(
+            tiles.x.div_ceil(WORKGROUP_SIZE),
+            tiles.y.div_ceil(WORKGROUP_SIZE),
+        )
+    }
+
+    /// Chunks to split the grid into, the last ones cut off where the grid ends, see [`chunk`].
+    pub fn chunks(&self) -> UVec2 {
+        UVec2::new(
+            self.size.x.div_ceil(chunk::CHUNK_SIZE),
+            self.size.y.div_ceil(chunk::CHUNK_SIZE),
+        )
+    }
+
+    pub fn num_chunks(&self) -> usize {
+        let chunks = self.chunks();
+        chunks.x as usize * chunks.y as usize
+    }
+
+    /// Workgroups to dispatch to cover every chunk, with an invocation per chunk.
+    pub fn chunk_workgroups(&self) -> UVec2 {
+        let chunks = self.chunks();
+        UVec2::new(
+            chunks.x.div_ceil(WORKGROUP_SIZE),
+            chunks.y.div_ceil(WORKGROUP_SIZE),
+        )
+    }
+
+    /// Size of the grid in the window, in logical pixels.
+    pub fn display_size(&self) -> Vec2 {
+        self.size.as_vec2() * self.display_factor as f32
+    }
+}
+
+/// Seed of the random choices of the simulation, from filling the grid in `init` to those made
+/// in every step, so that a run can be told apart from others and repeated. Insert it before
+/// adding [`GameOfLifeComputePlugin`] to start from another than the default, it is then kept in
+/// `AutomataParams::seed`.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SimulationSeed(pub u32);
+
+impl SimulationSeed {
+    /// A seed that differs from run to run, from the current time.
+    pub fn random() -> Self {
+        let nanos = bevy::utils::SystemTime::now()
+            .duration_since(bevy::utils::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        Self(simulation::hash(nanos))
+    }
+}
 
 pub struct GameOfLifeComputePlugin;
 
 impl Plugin for GameOfLifeComputePlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<GridConfig>();
+
         // Extract the game of life image resource from the main world into the render world
         // for operation on by the compute shader and display on the sprite.
         app.add_plugins(ExtractResourcePlugin::<GameOfLifeImage>::default())
+            .add_plugins(ExtractResourcePlugin::<GridConfig>::default())
             .add_plugins(ExtractResourcePlugin::<GameOfLifeBuffers>::default())
             .add_plugins(ExtractResourcePlugin::<AutomataParams>::default())
+            .add_plugins(ExtractResourcePlugin::<MaterialRegistry>::default())
+            .add_plugins(ExtractResourcePlugin::<ReactionTable>::default())
+            .add_plugins(material::MaterialPlugin)
+            .add_plugins(reaction::ReactionPlugin)
+            .add_plugins(readback::ReadbackPlugin)
+            .add_plugins(snapshot::SnapshotPlugin)
+            .add_plugins(import::ImportPlugin)
+            .add_plugins(screenshot::ScreenshotPlugin)
+            .add_plugins(recording::RecordingPlugin)
+            .add_plugins(replay::ReplayPlugin)
+            .add_plugins(resize::ResizePlugin)
+            .add_plugins(chunk::ChunkPlugin)
+            .add_plugins(tile::TilePlugin)
+            .add_plugins(speed::SpeedPlugin)
+            .add_plugins(rewind::RewindPlugin)
+            .add_plugins(reset::ResetPlugin)
             .add_plugins(input::InputPlugin)
             .add_systems(Startup, setup);
 
+        app.world_mut().resource_mut::<Assets<Shader>>().insert(
+            cell::CELL_SHADER_HANDLE.id(),
+            Shader::from_wgsl(cell::wgsl_module(), file!()),
+        );
+
         let render_app = app.sub_app_mut(RenderApp);
 
         let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        render_graph.add_node(AutomataDrawLabel, AutomataDrawNode::default());
         render_graph.add_node(GameOfLifeLabel, GameOfLifeNode::default());
         render_graph.add_node(AutomataColorLabel, AutomataColorNode::default());
 
+        render_graph.add_node_edge(AutomataDrawLabel, GameOfLifeLabel);
         render_graph.add_node_edge(GameOfLifeLabel, AutomataColorLabel);
         render_graph.add_node_edge(AutomataColorLabel, bevy::render::graph::CameraDriverLabel);
     }
 
     fn finish(&self, app: &mut App) {
+        // How large a grid the GPU can hold is only known now, so one that is too large is shrunk
+        // here rather than failing inside wgpu once its buffers are created
+        let limits = app.world().resource::<RenderDevice>().limits();
+        let config = *app.world().resource::<GridConfig>();
+        if let Err(err) = config.check_limits(&limits) {
+            let fitted = config.fit_limits(&limits);
+            error!("{err}, using {}x{} instead", fitted.size.x, fitted.size.y);
+            app.insert_resource(fitted);
+            let display_size = fitted.display_size();
+            let mut windows = app.world_mut().query::<&mut Window>();
+            for mut window in windows.iter_mut(app.world_mut()) {
+                window.resolution.set(display_size.x, display_size.y);
+            }
+        }
+
         let render_app = app.sub_app_mut(RenderApp);
         render_app
             .add_plugins(automata::AutomataPipelinePlugin)
-            .add_plugins(color::AutomataColorPipelinePlugin);
+            .add_plugins(color::AutomataColorPipelinePlugin)
+            .add_plugins(draw::AutomataDrawPipelinePlugin);
     }
 }
 
-fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>, device: Res<RenderDevice>) {
-    let mut image = Image::new_fill(
-        Extent3d {
-            width: SIZE.0,
-            height: SIZE.1,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        &[0, 0, 0, 255],
-        TextureFormat::Rgba8Unorm,
-        RenderAssetUsages::RENDER_WORLD,
-    );
-    image.texture_descriptor.usage =
-        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
-    let image = images.add(image);
+fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    device: Res<RenderDevice>,
+    config: Res<GridConfig>,
+) {
+    let image = images.add(create_image(config.size));
 
     commands.spawn(SpriteBundle {
         sprite: Sprite {
-            custom_size: Some(Vec2::new(SIZE.0 as f32, SIZE.1 as f32)),
+            custom_size: Some(config.size.as_vec2()),
             ..default()
         },
         texture: image.clone(),
-        transform: Transform::from_scale(Vec3::splat(DISPLAY_FACTOR as f32)),
+        transform: Transform::from_scale(Vec3::splat(config.display_factor as f32)),
 
         ..default()
     });
 
-    let initial_life_data = vec![Cell::default(); NUM_OF_CELLS];
-    let buffers_in_out = (0..2)
-        .map(|i| {
-            utils::create_storage_buffer_with_data(
-                &device,
-                &initial_life_data,
-                Some(&format!("Game of Life Buffer {i}")),
-            )
-        })
-        .collect::<Vec<_>>();
-
-    let buffer_size =
-        utils::create_uniform_buffer(&device, &[SIZE.0, SIZE.1], Some("Size Uniform Buffer"));
-
     commands.insert_resource(GameOfLifeImage { texture: image });
-    commands.insert_resource(GameOfLifeBuffers {
-        size: buffer_size,
-        in_out: buffers_in_out,
-    });
+    commands.insert_resource(GameOfLifeBuffers::new(&device, &config));
 
     commands.spawn((
         Camera2dBundle {
@@ -115,5 +251,26 @@ fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>, device: Res<
         },
         BloomSettings::default(),
     ));
-    commands.spawn(PerfUiBundle::default());
+    commands.spawn((PerfUiBundle::default(), PerfUiEntryActiveTiles::default()));
+}
+
+/// The texture that the cells are colored into, see `color.wgsl`.
+fn create_image(size: UVec2) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    // COPY_SRC so that it can be read back for screenshots, see `readback`
+    image.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING;
+    image
 }
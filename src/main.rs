@@ -5,20 +5,83 @@
 
 use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*};
 use iyes_perf_ui::prelude::*;
-use litterbox::{GameOfLifeComputePlugin, DISPLAY_FACTOR, SIZE};
+use litterbox::{
+    import::ImportImage, replay::PlayReplay, reset::Initializer, tile::PerfUiEntryActiveTiles,
+    GameOfLifeComputePlugin, GridConfig, SimulationSeed,
+};
+
+const USAGE: &str = "usage: litterbox [--config CONFIG] [--size WIDTHxHEIGHT] [--scale PIXELS] \
+                     [--seed SEED] [--replay REPLAY] [LEVEL_IMAGE]";
 
 fn main() {
-    App::new()
-        .insert_resource(ClearColor(Color::BLACK))
+    let mut config_path = None;
+    let mut size = None;
+    let mut scale = None;
+    // A different run each time unless asked for a particular one
+    let mut seed = SimulationSeed::random();
+    let mut level = None;
+    let mut replay = None;
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            let Some(path) = args.next() else {
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            };
+            config_path = Some(path);
+        } else if arg == "--size" {
+            let Some(value) = args.next().and_then(|size| parse_size(size.to_str()?)) else {
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            };
+            size = Some(value);
+        } else if arg == "--scale" {
+            let Some(value) = args
+                .next()
+                .and_then(|scale| scale.to_str()?.parse().ok())
+                .filter(|scale| *scale > 0)
+            else {
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            };
+            scale = Some(value);
+        } else if arg == "--seed" {
+            let Some(value) = args.next().and_then(|seed| seed.to_str()?.parse().ok()) else {
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            };
+            seed = SimulationSeed(value);
+        } else if arg == "--replay" {
+            let Some(path) = args.next() else {
+                eprintln!("{USAGE}");
+                std::process::exit(2);
+            };
+            replay = Some(path);
+        } else {
+            level = Some(arg);
+        }
+    }
+
+    // The flags take precedence over the config file, see `litterbox::config`
+    let mut config = match config_path {
+        Some(path) => GridConfig::load(&path).unwrap_or_else(|err| {
+            eprintln!("{}: {err}", path.to_string_lossy());
+            std::process::exit(2);
+        }),
+        None => GridConfig::default(),
+    };
+    config.size = size.unwrap_or(config.size);
+    config.display_factor = scale.unwrap_or(config.display_factor);
+
+    let mut app = App::new();
+    app.insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(config)
+        .insert_resource(seed)
         .add_plugins((
             DefaultPlugins
                 .set(WindowPlugin {
                     primary_window: Some(Window {
-                        resolution: (
-                            (SIZE.0 * DISPLAY_FACTOR) as f32,
-                            (SIZE.1 * DISPLAY_FACTOR) as f32,
-                        )
-                            .into(),
+                        resolution: config.display_size().into(),
                         // uncomment for unthrottled FPS
                         // present_mode: bevy::window::PresentMode::AutoNoVsync,
                         ..default()
@@ -30,5 +93,34 @@ fn main() {
             PerfUiPlugin,
             GameOfLifeComputePlugin,
         ))
-        .run();
+        // Spawned with the rest of the perf UI, see `litterbox::tile`
+        .add_perf_ui_simple_entry::<PerfUiEntryActiveTiles>();
+
+    // Start from a level image instead of the random `init` pass, see `litterbox::import`, and
+    // start it over when the world is reset
+    if let Some(path) = level {
+        app.insert_resource(Initializer::Image(path.clone().into()));
+        app.add_systems(Startup, move |mut imports: EventWriter<ImportImage>| {
+            imports.send(ImportImage {
+                path: path.clone().into(),
+                fit: default(),
+            });
+        });
+    }
+
+    // Reproduce a recorded session, see `litterbox::replay`
+    if let Some(path) = replay {
+        app.add_systems(Startup, move |mut plays: EventWriter<PlayReplay>| {
+            plays.send(PlayReplay(path.clone().into()));
+        });
+    }
+
+    app.run();
+}
+
+// `256x128`, both at least 1
+fn parse_size(size: &str) -> Option<UVec2> {
+    let (width, height) = size.split_once('x')?;
+    let size = UVec2::new(width.parse().ok()?, height.parse().ok()?);
+    size.cmpgt(UVec2::ZERO).all().then_some(size)
 }
@@ -0,0 +1,395 @@
+//! Data-driven materials.
+//!
+//! Every material the simulation knows about is described in `assets/litterbox.materials.ron`.
+//! The position of a material in that file is its `type_id`, which is what cells store and
+//! what the shaders index the uploaded [`GpuMaterial`] table with.
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    render::extract_resource::ExtractResource,
+};
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+use thiserror::Error;
+
+const MATERIALS_ASSET_PATH: &str = "litterbox.materials.ron";
+
+/// Upper bound on the number of materials, used to size the GPU material buffer.
+pub const MAX_MATERIALS: usize = 64;
+
+/// Upper bound on [`MaterialDescriptor::dispersion`], see `MAX_DISPERSION` in `core.wgsl`.
+pub const MAX_DISPERSION: u32 = 8;
+
+/// Temperature new cells start at unless their material says otherwise, in degrees Celsius.
+pub const AMBIENT_TEMPERATURE: f32 = 20.;
+
+/// Upper bound on [`MaterialDescriptor::conductivity`]. Higher values would let heat
+/// overshoot when a cell exchanges heat with all four neighbors in the same step.
+pub const MAX_CONDUCTIVITY: f32 = 0.25;
+
+/// How a material moves. Must be kept in sync with the `STATE_*` constants in `core.wgsl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[repr(u32)]
+pub enum MaterialState {
+    /// Nothing there; other materials move freely through it.
+    #[default]
+    Empty = 0,
+    /// Never moves.
+    Solid = 1,
+    /// Falls straight down, then diagonally.
+    Powder = 2,
+    Liquid = 3,
+    Gas = 4,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaterialDescriptor {
+    pub name: String,
+    pub state: MaterialState,
+    /// Base RGBA color.
+    pub color: [f32; 4],
+    /// Maximum random offset applied to each RGB channel of a new cell's color.
+    #[serde(default)]
+    pub color_jitter: f32,
+    #[serde(default)]
+    pub density: f32,
+    /// Chance in `[0, 1]` per step of catching fire from a touching cell of
+    /// [`Self::burns_into`]. Stands for a `burns_into + material -> burns_into + burns_into`
+    /// reaction, see [`crate::reaction`], unless one is listed there.
+    #[serde(default)]
+    pub flammability: f32,
+    /// Name of the material that a flammable material catches fire from and turns into.
+    #[serde(default)]
+    pub burns_into: Option<String>,
+    /// How many cells a liquid or gas may move sideways per step, at most [`MAX_DISPERSION`].
+    #[serde(default)]
+    pub dispersion: u32,
+    /// Average number of steps a cell lasts before turning into [`Self::decays_into`].
+    /// 0 means it lasts forever.
+    #[serde(default)]
+    pub lifetime: u32,
+    /// Name of the material a cell turns into once its lifetime runs out. Defaults to the
+    /// empty material.
+    #[serde(default)]
+    pub decays_into: Option<String>,
+    /// Temperature new cells start at, in degrees Celsius.
+    #[serde(default = "ambient_temperature")]
+    pub temperature: f32,
+    /// Fraction of the temperature difference with each neighbor that is exchanged per step,
+    /// at most [`MAX_CONDUCTIVITY`]. Between two materials, the lower conductivity wins.
+    #[serde(default)]
+    pub conductivity: f32,
+    /// What the material turns into when it gets hotter than the given temperature.
+    #[serde(default)]
+    pub heats_into: Option<PhaseTransition>,
+    /// What the material turns into when it gets colder than the given temperature.
+    #[serde(default)]
+    pub cools_into: Option<PhaseTransition>,
+}
+
+impl MaterialDescriptor {
+    /// Names of the other materials this one refers to.
+    fn referenced_materials(&self) -> impl Iterator<Item = &str> {
+        let transitions = [&self.heats_into, &self.cools_into]
+            .into_iter()
+            .flatten()
+            .map(|transition| transition.into.as_str());
+        [&self.decays_into, &self.burns_into]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(transitions)
+    }
+}
+
+fn ambient_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PhaseTransition {
+    pub temperature: f32,
+    /// Name of the material to turn into.
+    pub into: String,
+}
+
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct MaterialTable {
+    pub materials: Vec<MaterialDescriptor>,
+}
+
+impl MaterialTable {
+    /// Parses a material table in the format of `assets/litterbox.materials.ron`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MaterialTableError> {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MaterialTableError {
+    #[error("could not read material table: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse material table: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("material table is empty; the first material must be the empty material (Air)")]
+    MissingEmpty,
+    #[error("material `{0}` is the first material but is not in the `Empty` state")]
+    FirstNotEmpty(String),
+    #[error("material table has {0} materials, at most {MAX_MATERIALS} are supported")]
+    TooManyMaterials(usize),
+    #[error("material `{0}` has a dispersion of {1}, at most {MAX_DISPERSION} is supported")]
+    DispersionTooHigh(String, u32),
+    #[error("material `{0}` has a conductivity of {1}, it must be in [0, {MAX_CONDUCTIVITY}]")]
+    ConductivityOutOfRange(String, f32),
+    #[error("material `{0}` has a flammability of {1}, it must be in [0, 1]")]
+    FlammabilityOutOfRange(String, f32),
+    #[error("material `{0}` is flammable but doesn't say what it `burns_into`")]
+    MissingBurnsInto(String),
+    #[error("material `{0}` refers to `{1}`, which is not in the material table")]
+    UnknownMaterial(String, String),
+}
+
+#[derive(Default)]
+pub struct MaterialTableLoader;
+
+impl AssetLoader for MaterialTableLoader {
+    type Asset = MaterialTable;
+    type Settings = ();
+    type Error = MaterialTableError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<MaterialTable, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        MaterialTable::from_bytes(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["materials.ron"]
+    }
+}
+
+/// Material layout as seen by the shaders, see `Material` in `core.wgsl`.
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct GpuMaterial {
+    pub color: [f32; 4],
+    pub color_jitter: f32,
+    pub density: f32,
+    pub state: u32,
+    /// `type_id` to turn into once the lifetime runs out.
+    pub decays_into: i32,
+    pub dispersion: u32,
+    pub lifetime: u32,
+    pub temperature: f32,
+    pub conductivity: f32,
+    pub heats_above: f32,
+    /// `type_id` to turn into above `heats_above`, -1 if none.
+    pub heats_into: i32,
+    pub cools_below: f32,
+    /// `type_id` to turn into below `cools_below`, -1 if none.
+    pub cools_into: i32,
+}
+
+/// The loaded material table. Only inserted once the asset has loaded and validated, and
+/// replaced whenever the asset is modified.
+#[derive(Resource, Debug, Clone, ExtractResource)]
+pub struct MaterialRegistry {
+    materials: Vec<MaterialDescriptor>,
+}
+
+impl MaterialRegistry {
+    pub fn new(materials: Vec<MaterialDescriptor>) -> Result<Self, MaterialTableError> {
+        let Some(first) = materials.first() else {
+            return Err(MaterialTableError::MissingEmpty);
+        };
+        if first.state != MaterialState::Empty {
+            return Err(MaterialTableError::FirstNotEmpty(first.name.clone()));
+        }
+        if materials.len() > MAX_MATERIALS {
+            return Err(MaterialTableError::TooManyMaterials(materials.len()));
+        }
+        if let Some(material) = materials
+            .iter()
+            .find(|material| material.dispersion > MAX_DISPERSION)
+        {
+            return Err(MaterialTableError::DispersionTooHigh(
+                material.name.clone(),
+                material.dispersion,
+            ));
+        }
+        if let Some(material) = materials
+            .iter()
+            .find(|material| !(0. ..=MAX_CONDUCTIVITY).contains(&material.conductivity))
+        {
+            return Err(MaterialTableError::ConductivityOutOfRange(
+                material.name.clone(),
+                material.conductivity,
+            ));
+        }
+        if let Some(material) = materials
+            .iter()
+            .find(|material| !(0. ..=1.).contains(&material.flammability))
+        {
+            return Err(MaterialTableError::FlammabilityOutOfRange(
+                material.name.clone(),
+                material.flammability,
+            ));
+        }
+        if let Some(material) = materials
+            .iter()
+            .find(|material| material.flammability > 0. && material.burns_into.is_none())
+        {
+            return Err(MaterialTableError::MissingBurnsInto(material.name.clone()));
+        }
+        let registry = Self { materials };
+        for material in &registry.materials {
+            if let Some(name) = material
+                .referenced_materials()
+                .find(|name| registry.id(name).is_none())
+            {
+                return Err(MaterialTableError::UnknownMaterial(
+                    material.name.clone(),
+                    name.to_owned(),
+                ));
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Looks up a material's `type_id` by name, ignoring case.
+    pub fn id(&self, name: &str) -> Option<i32> {
+        self.materials
+            .iter()
+            .position(|material| material.name.eq_ignore_ascii_case(name))
+            .map(|index| index as i32)
+    }
+
+    /// Changes whenever a material is added, removed, renamed or moved, that is whenever a
+    /// `type_id` may come to mean another material. Stable across builds, see [`crate::snapshot`].
+    pub fn fingerprint(&self) -> u64 {
+        // FNV-1a
+        let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+        for material in &self.materials {
+            for byte in material.name.bytes().chain([0]) {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// Name of the material with the given `type_id`.
+    pub fn name(&self, type_id: i32) -> Option<&str> {
+        let material = self.materials.get(usize::try_from(type_id).ok()?)?;
+        Some(&material.name)
+    }
+
+    /// Chance per step that a cell of the material with the given `type_id` catches fire.
+    pub fn flammability(&self, type_id: i32) -> f32 {
+        usize::try_from(type_id)
+            .ok()
+            .and_then(|index| self.materials.get(index))
+            .map_or(0., |material| material.flammability)
+    }
+
+    /// `type_id` of the material that the material with the given `type_id` catches fire from
+    /// and turns into, if it is flammable.
+    pub fn burns_into(&self, type_id: i32) -> Option<i32> {
+        let material = self.materials.get(usize::try_from(type_id).ok()?)?;
+        // Names were checked in `new`
+        material
+            .burns_into
+            .as_ref()
+            .filter(|_| material.flammability > 0.)
+            .map(|name| self.id(name).unwrap())
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    /// Never true, a valid table always has at least the empty material.
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    /// The table to upload into `GameOfLifeBuffers::materials`, padded to `MAX_MATERIALS`.
+    pub fn to_gpu(&self) -> Vec<GpuMaterial> {
+        let mut gpu_materials: Vec<GpuMaterial> = self
+            .materials
+            .iter()
+            .map(|material| self.gpu_material(material))
+            .collect();
+        gpu_materials.resize(MAX_MATERIALS, GpuMaterial::default());
+        gpu_materials
+    }
+
+    fn gpu_material(&self, material: &MaterialDescriptor) -> GpuMaterial {
+        // Names were checked in `new`
+        let id = |name: &Option<String>| name.as_ref().map_or(-1, |name| self.id(name).unwrap());
+        let transition = |transition: &Option<PhaseTransition>| match transition {
+            Some(transition) => (transition.temperature, self.id(&transition.into).unwrap()),
+            None => (0., -1),
+        };
+        let (heats_above, heats_into) = transition(&material.heats_into);
+        let (cools_below, cools_into) = transition(&material.cools_into);
+        GpuMaterial {
+            color: material.color,
+            color_jitter: material.color_jitter,
+            density: material.density,
+            state: material.state as u32,
+            // The empty material unless stated otherwise
+            decays_into: id(&material.decays_into).max(0),
+            dispersion: material.dispersion,
+            lifetime: material.lifetime,
+            temperature: material.temperature,
+            conductivity: material.conductivity,
+            heats_above,
+            heats_into,
+            cools_below,
+            cools_into,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct MaterialTableHandle(Handle<MaterialTable>);
+
+pub struct MaterialPlugin;
+impl Plugin for MaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<MaterialTable>()
+            .init_asset_loader::<MaterialTableLoader>()
+            .add_systems(Startup, load_material_table)
+            .add_systems(Update, update_material_registry);
+    }
+}
+
+fn load_material_table(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(MaterialTableHandle(asset_server.load(MATERIALS_ASSET_PATH)));
+}
+
+fn update_material_registry(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<MaterialTable>>,
+    handle: Res<MaterialTableHandle>,
+    tables: Res<Assets<MaterialTable>>,
+) {
+    for event in events.read() {
+        if !event.is_loaded_with_dependencies(&handle.0) && !event.is_modified(&handle.0) {
+            continue;
+        }
+        let Some(table) = tables.get(&handle.0) else {
+            continue;
+        };
+        match MaterialRegistry::new(table.materials.clone()) {
+            Ok(registry) => commands.insert_resource(registry),
+            Err(err) => error!("assets/{MATERIALS_ASSET_PATH}: {err}"),
+        }
+    }
+}
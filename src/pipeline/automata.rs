@@ -10,10 +10,25 @@ use bevy::{
 };
 use std::{borrow::Cow, sync::atomic::Ordering};
 
-use crate::{AutomataParams, NUM_OF_CELLS, SIZE, WORKGROUP_SIZE};
+use crate::{
+    cell::Cell,
+    material::{GpuMaterial, MaterialRegistry, MAX_MATERIALS},
+    reaction::{GpuReaction, ReactionTable},
+    readback::Readback,
+    replay::ReplayInputs,
+    reset::WorldResets,
+    rewind::RewindBuffers,
+    simulation::InitUniform,
+    snapshot::LoadedSnapshot,
+    speed::MAX_STEPS_PER_FRAME,
+    utils, AutomataParams, GridConfig,
+};
 
 const SHADER_ASSET_PATH: &str = "shaders/litterbox.wgsl";
+const CHUNKS_SHADER_ASSET_PATH: &str = "shaders/chunks.wgsl";
 
+// Buffers with an entry per cell only ask for one, so that the layouts, and the pipelines built
+// on them, still fit after the grid has been resized, see `resize`
 pub const BIND_GROUP_LAYOUT_ENTRY_CELL: BindGroupLayoutEntry = BindGroupLayoutEntry {
     binding: u32::MAX,
     count: None,
@@ -21,12 +36,55 @@ pub const BIND_GROUP_LAYOUT_ENTRY_CELL: BindGroupLayoutEntry = BindGroupLayoutEn
     ty: BindingType::Buffer {
         ty: BufferBindingType::Storage { read_only: false },
         has_dynamic_offset: false,
-        min_binding_size: BufferSize::new(
-            (NUM_OF_CELLS
-                * (std::mem::size_of::<u32>()            // alive: u32
-                    + 4 * (std::mem::size_of::<f32>()))) // color: vec4<f32>
-                as _,
-        ),
+        min_binding_size: BufferSize::new(std::mem::size_of::<Cell>() as _),
+    },
+};
+
+pub const BIND_GROUP_LAYOUT_ENTRY_MATERIALS: BindGroupLayoutEntry = BindGroupLayoutEntry {
+    binding: u32::MAX,
+    count: None,
+    visibility: ShaderStages::COMPUTE,
+    ty: BindingType::Buffer {
+        ty: BufferBindingType::Storage { read_only: true },
+        has_dynamic_offset: false,
+        min_binding_size: BufferSize::new(std::mem::size_of::<GpuMaterial>() as _),
+    },
+};
+
+/// `GameOfLifeBuffers::tile_activity` and `tile_colors`, set by whatever changes the cells of a
+/// tile.
+pub const BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS: BindGroupLayoutEntry = BindGroupLayoutEntry {
+    binding: u32::MAX,
+    count: None,
+    visibility: ShaderStages::COMPUTE,
+    ty: BindingType::Buffer {
+        ty: BufferBindingType::Storage { read_only: false },
+        has_dynamic_offset: false,
+        min_binding_size: BufferSize::new(std::mem::size_of::<u32>() as _),
+    },
+};
+
+/// The chunks that an indirect dispatch goes over, see `plan_chunks` in `chunks.wgsl`.
+const BIND_GROUP_LAYOUT_ENTRY_CHUNK_LIST: BindGroupLayoutEntry = BindGroupLayoutEntry {
+    binding: u32::MAX,
+    count: None,
+    visibility: ShaderStages::COMPUTE,
+    ty: BindingType::Buffer {
+        ty: BufferBindingType::Storage { read_only: false },
+        has_dynamic_offset: false,
+        min_binding_size: BufferSize::new(std::mem::size_of::<UVec2>() as _),
+    },
+};
+
+/// The arguments of an indirect dispatch, written by the shader that plans it.
+const BIND_GROUP_LAYOUT_ENTRY_DISPATCH: BindGroupLayoutEntry = BindGroupLayoutEntry {
+    binding: u32::MAX,
+    count: None,
+    visibility: ShaderStages::COMPUTE,
+    ty: BindingType::Buffer {
+        ty: BufferBindingType::Storage { read_only: false },
+        has_dynamic_offset: false,
+        min_binding_size: BufferSize::new((3 * std::mem::size_of::<u32>()) as _),
     },
 };
 
@@ -37,7 +95,11 @@ impl Plugin for AutomataPipelinePlugin {
             .init_resource::<GameOfLifePipeline>()
             .add_systems(
                 Render,
-                prepare_automata_bind_group.in_set(RenderSet::PrepareBindGroups),
+                (
+                    prepare_material_buffers.in_set(RenderSet::PrepareResources),
+                    prepare_reaction_buffer.in_set(RenderSet::PrepareResources),
+                    prepare_automata_bind_group.in_set(RenderSet::PrepareBindGroups),
+                ),
             );
     }
 }
@@ -54,12 +116,271 @@ pub struct GameOfLifeImage {
 pub struct GameOfLifeBuffers {
     pub size: Buffer,
     pub in_out: Vec<Buffer>,
+    pub materials: Buffer,
+    pub init: Buffer,
+    pub frame: Buffer,
+    /// The step numbers of the steps taken this frame, copied into `frame` before each of them.
+    pub step_frames: Buffer,
+    /// `AutomataParams::seed`, see [`crate::SimulationSeed`].
+    pub seed: Buffer,
+    /// Where each cell wants to move this step, see `claim` in `litterbox.wgsl`.
+    pub intents: Buffer,
+    /// Temperature of each cell after heat has spread, see `diffuse_heat` in `litterbox.wgsl`.
+    pub temperatures: Buffer,
+    /// The [`ReactionTable`].
+    pub reactions: Buffer,
+    /// What each cell turns into this step, see `react` in `litterbox.wgsl`.
+    pub products: Buffer,
+    /// Whether anything in each tile changed during the last step, see `chunks.wgsl`.
+    pub tile_activity: Buffer,
+    /// Steps since anything in each tile last changed.
+    pub tile_quiet: Buffer,
+    /// Whether each tile is stepped.
+    pub tile_awake: Buffer,
+    /// The chunks with awake tiles, that the step is dispatched over.
+    pub awake_chunks: Buffer,
+    /// Arguments of the indirect dispatches of the step, one layer of workgroups per awake chunk.
+    pub dispatch: Buffer,
+    /// Whether the cells of each tile have changed since they were last colored.
+    pub tile_colors: Buffer,
+    /// The chunks with tiles to color, that the color pass is dispatched over.
+    pub color_chunks: Buffer,
+    /// Arguments of the indirect dispatch of the color pass.
+    pub color_dispatch: Buffer,
+    /// Counts of the tiles stepped and colored, see [`crate::tile::TileStats`].
+    pub tile_stats: Buffer,
+}
+
+/// The buffers whose size depends on that of the grid.
+struct GridBuffers {
+    size: Buffer,
+    in_out: Vec<Buffer>,
+    intents: Buffer,
+    temperatures: Buffer,
+    products: Buffer,
+    tile_activity: Buffer,
+    tile_quiet: Buffer,
+    tile_awake: Buffer,
+    awake_chunks: Buffer,
+    dispatch: Buffer,
+    tile_colors: Buffer,
+    color_chunks: Buffer,
+    color_dispatch: Buffer,
+}
+
+impl GridBuffers {
+    fn new(device: &RenderDevice, config: &GridConfig, cells: &[Cell]) -> Self {
+        let num_cells = config.num_cells();
+        let num_tiles = config.num_tiles();
+        let num_chunks = config.num_chunks();
+        Self {
+            size: utils::create_uniform_buffer(
+                device,
+                &config.size.to_array(),
+                Some("Size Uniform Buffer"),
+            ),
+            in_out: (0..2)
+                .map(|i| {
+                    utils::create_storage_buffer_with_data(
+                        device,
+                        cells,
+                        Some(&format!("Game of Life Buffer {i}")),
+                    )
+                })
+                .collect(),
+            intents: utils::create_storage_buffer_with_data(
+                device,
+                &vec![[0i32; 2]; num_cells],
+                Some("Intents Buffer"),
+            ),
+            temperatures: utils::create_storage_buffer_with_data(
+                device,
+                &vec![0f32; num_cells],
+                Some("Temperatures Buffer"),
+            ),
+            products: utils::create_storage_buffer_with_data(
+                device,
+                &vec![-1i32; num_cells],
+                Some("Products Buffer"),
+            ),
+            tile_activity: utils::create_storage_buffer_with_data(
+                device,
+                &vec![0u32; num_tiles],
+                Some("Tile Activity Buffer"),
+            ),
+            // Every tile starts out awake
+            tile_quiet: utils::create_storage_buffer_with_data(
+                device,
+                &vec![0u32; num_tiles],
+                Some("Tile Quiet Buffer"),
+            ),
+            tile_awake: utils::create_storage_buffer_with_data(
+                device,
+                &vec![0u32; num_tiles],
+                Some("Tile Awake Buffer"),
+            ),
+            awake_chunks: utils::create_storage_buffer_with_data(
+                device,
+                &vec![[0u32; 2]; num_chunks],
+                Some("Awake Chunks Buffer"),
+            ),
+            // Filled in before each step, see `plan_chunks` in `chunks.wgsl`
+            dispatch: utils::create_indirect_buffer(device, &[0u32; 3], Some("Dispatch Buffer")),
+            // and the new texture colored all over
+            tile_colors: utils::create_storage_buffer_with_data(
+                device,
+                &vec![1u32; num_tiles],
+                Some("Tile Colors Buffer"),
+            ),
+            color_chunks: utils::create_storage_buffer_with_data(
+                device,
+                &vec![[0u32; 2]; num_chunks],
+                Some("Color Chunks Buffer"),
+            ),
+            // Filled in before each color pass, see `plan_colors` in `chunks.wgsl`
+            color_dispatch: utils::create_indirect_buffer(
+                device,
+                &[0u32; 3],
+                Some("Color Dispatch Buffer"),
+            ),
+        }
+    }
+}
+
+impl GameOfLifeBuffers {
+    pub fn new(device: &RenderDevice, config: &GridConfig) -> Self {
+        let GridBuffers {
+            size,
+            in_out,
+            intents,
+            temperatures,
+            products,
+            tile_activity,
+            tile_quiet,
+            tile_awake,
+            awake_chunks,
+            dispatch,
+            tile_colors,
+            color_chunks,
+            color_dispatch,
+        } = GridBuffers::new(device, config, &vec![Cell::default(); config.num_cells()]);
+        Self {
+            size,
+            in_out,
+            // Filled in once the material table has loaded, see `prepare_material_buffers`
+            materials: utils::create_storage_buffer_with_data(
+                device,
+                &[GpuMaterial::default(); MAX_MATERIALS],
+                Some("Materials Buffer"),
+            ),
+            init: utils::create_uniform_buffer(
+                device,
+                &[InitUniform::default()],
+                Some("Init Uniform Buffer"),
+            ),
+            frame: utils::create_uniform_buffer(device, &[0u32], Some("Frame Uniform Buffer")),
+            step_frames: utils::create_storage_buffer_with_data(
+                device,
+                &[0u32; MAX_STEPS_PER_FRAME],
+                Some("Step Frames Buffer"),
+            ),
+            seed: utils::create_uniform_buffer(device, &[0u32], Some("Seed Uniform Buffer")),
+            intents,
+            temperatures,
+            // Filled in once the reactions have loaded, see `prepare_reaction_buffer`
+            reactions: utils::create_storage_buffer_with_data(
+                device,
+                &vec![GpuReaction::default(); MAX_MATERIALS * MAX_MATERIALS],
+                Some("Reactions Buffer"),
+            ),
+            products,
+            tile_activity,
+            tile_quiet,
+            tile_awake,
+            awake_chunks,
+            dispatch,
+            tile_colors,
+            color_chunks,
+            color_dispatch,
+            tile_stats: utils::create_storage_buffer_with_data(
+                device,
+                &[0u32; 2],
+                Some("Tile Stats Buffer"),
+            ),
+        }
+    }
+
+    /// Replaces every buffer that depends on the size of the grid with one for `config`, with
+    /// `cells` in both `in_out` buffers. The material and reaction tables are kept.
+    pub fn resize(&mut self, device: &RenderDevice, config: &GridConfig, cells: &[Cell]) {
+        let GridBuffers {
+            size,
+            in_out,
+            intents,
+            temperatures,
+            products,
+            tile_activity,
+            tile_quiet,
+            tile_awake,
+            awake_chunks,
+            dispatch,
+            tile_colors,
+            color_chunks,
+            color_dispatch,
+        } = GridBuffers::new(device, config, cells);
+        self.size = size;
+        self.in_out = in_out;
+        self.intents = intents;
+        self.temperatures = temperatures;
+        self.products = products;
+        self.tile_activity = tile_activity;
+        self.tile_quiet = tile_quiet;
+        self.tile_awake = tile_awake;
+        self.awake_chunks = awake_chunks;
+        self.dispatch = dispatch;
+        self.tile_colors = tile_colors;
+        self.color_chunks = color_chunks;
+        self.color_dispatch = color_dispatch;
+    }
+
+    fn num_tiles(&self) -> usize {
+        (self.tile_quiet.size() / std::mem::size_of::<u32>() as u64) as usize
+    }
+
+    /// Wakes every tile for the next steps and has them colored again, after the cells have been
+    /// changed from outside of the step.
+    pub fn wake(&self, render_queue: &RenderQueue) {
+        render_queue.write_buffer(
+            &self.tile_quiet,
+            0,
+            bytemuck::cast_slice(&vec![0u32; self.num_tiles()]),
+        );
+        self.recolor(render_queue);
+    }
+
+    /// Has every tile colored again, when they are to be colored differently.
+    pub fn recolor(&self, render_queue: &RenderQueue) {
+        render_queue.write_buffer(
+            &self.tile_colors,
+            0,
+            bytemuck::cast_slice(&vec![1u32; self.num_tiles()]),
+        );
+    }
 }
 
 #[derive(Resource)]
 pub struct GameOfLifePipeline {
     texture_bind_group_layout: BindGroupLayout,
+    chunk_bind_group_layout: BindGroupLayout,
     init_pipeline: CachedComputePipelineId,
+    settle_pipeline: CachedComputePipelineId,
+    plan_tiles_pipeline: CachedComputePipelineId,
+    plan_chunks_pipeline: CachedComputePipelineId,
+    /// Run by the color pass, see `AutomataColorNode`.
+    pub plan_colors_pipeline: CachedComputePipelineId,
+    heat_pipeline: CachedComputePipelineId,
+    react_pipeline: CachedComputePipelineId,
+    claim_pipeline: CachedComputePipelineId,
     update_pipeline: CachedComputePipelineId,
 }
 
@@ -85,10 +406,151 @@ impl FromWorld for GameOfLifePipeline {
                     },
                     BIND_GROUP_LAYOUT_ENTRY_CELL,
                     BIND_GROUP_LAYOUT_ENTRY_CELL,
+                    BIND_GROUP_LAYOUT_ENTRY_MATERIALS,
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<InitUniform>() as _
+                            ),
+                        },
+                    },
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<u32>() as _),
+                        },
+                    },
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<IVec2>() as _),
+                        },
+                    },
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<f32>() as _),
+                        },
+                    },
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                (MAX_MATERIALS * MAX_MATERIALS * std::mem::size_of::<GpuReaction>())
+                                    as _,
+                            ),
+                        },
+                    },
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<i32>() as _),
+                        },
+                    },
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<UVec2>() as _),
+                        },
+                    },
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<u32>() as _),
+                        },
+                    },
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<u32>() as _),
+                        },
+                    },
+                ),
+            ),
+        );
+        // Separate from those of the step and the color pass, which can't have the arguments of
+        // their indirect dispatches bound while they are dispatched with them
+        let chunk_bind_group_layout = render_device.create_bind_group_layout(
+            "Chunks Bind Group Layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                (2 * std::mem::size_of::<u32>()) as _,
+                            ),
+                        },
+                    },
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BIND_GROUP_LAYOUT_ENTRY_CHUNK_LIST,
+                    BIND_GROUP_LAYOUT_ENTRY_DISPATCH,
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BIND_GROUP_LAYOUT_ENTRY_CHUNK_LIST,
+                    BIND_GROUP_LAYOUT_ENTRY_DISPATCH,
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                (2 * std::mem::size_of::<u32>()) as _,
+                            ),
+                        },
+                    },
                 ),
             ),
         );
         let shader = world.load_asset(SHADER_ASSET_PATH);
+        let chunks_shader = world.load_asset(CHUNKS_SHADER_ASSET_PATH);
         let pipeline_cache = world.resource::<PipelineCache>();
         let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
             label: None,
@@ -98,6 +560,65 @@ impl FromWorld for GameOfLifePipeline {
             shader_defs: vec![],
             entry_point: Cow::from("init"),
         });
+        let settle_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![chunk_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: chunks_shader.clone(),
+            shader_defs: vec![],
+            entry_point: Cow::from("settle_tiles"),
+        });
+        let plan_tiles_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: vec![chunk_bind_group_layout.clone()],
+                push_constant_ranges: Vec::new(),
+                shader: chunks_shader.clone(),
+                shader_defs: vec![],
+                entry_point: Cow::from("plan_tiles"),
+            });
+        let plan_chunks_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: vec![chunk_bind_group_layout.clone()],
+                push_constant_ranges: Vec::new(),
+                shader: chunks_shader.clone(),
+                shader_defs: vec![],
+                entry_point: Cow::from("plan_chunks"),
+            });
+        let plan_colors_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: vec![chunk_bind_group_layout.clone()],
+                push_constant_ranges: Vec::new(),
+                shader: chunks_shader,
+                shader_defs: vec![],
+                entry_point: Cow::from("plan_colors"),
+            });
+        let heat_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![texture_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: vec![],
+            entry_point: Cow::from("diffuse_heat"),
+        });
+        let react_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![texture_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: vec![],
+            entry_point: Cow::from("react"),
+        });
+        let claim_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![texture_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: vec![],
+            entry_point: Cow::from("claim"),
+        });
         let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
             label: None,
             layout: vec![texture_bind_group_layout.clone()],
@@ -109,41 +630,129 @@ impl FromWorld for GameOfLifePipeline {
 
         GameOfLifePipeline {
             texture_bind_group_layout,
+            chunk_bind_group_layout,
             init_pipeline,
+            settle_pipeline,
+            plan_tiles_pipeline,
+            plan_chunks_pipeline,
+            plan_colors_pipeline,
+            heat_pipeline,
+            react_pipeline,
+            claim_pipeline,
             update_pipeline,
         }
     }
 }
 
+/// One for each buffer of `in_out` to read from, indexed by the parity of the step number.
 #[derive(Resource)]
-pub struct GameOfLifeImageBindGroup(pub BindGroup);
+pub struct GameOfLifeImageBindGroups(pub [BindGroup; 2]);
+
+#[derive(Resource)]
+pub struct GameOfLifeChunkBindGroup(pub BindGroup);
+
+// Upload the material table whenever it is (re)loaded, and what `init` places whenever either it
+// or the pattern to place changes
+pub fn prepare_material_buffers(
+    render_queue: Res<RenderQueue>,
+    buffers: Res<GameOfLifeBuffers>,
+    registry: Option<Res<MaterialRegistry>>,
+    resets: Option<Res<WorldResets>>,
+) {
+    let Some(registry) = registry else {
+        return;
+    };
+    if registry.is_changed() {
+        render_queue.write_buffer(
+            &buffers.materials,
+            0,
+            bytemuck::cast_slice(&registry.to_gpu()),
+        );
+    }
+    let is_reset = resets.as_ref().is_some_and(|resets| resets.is_changed());
+    if registry.is_changed() || is_reset {
+        let pattern = resets.map(|resets| resets.pattern).unwrap_or_default();
+        let init = InitUniform::from(registry.as_ref()).with_pattern(pattern);
+        render_queue.write_buffer(&buffers.init, 0, bytemuck::bytes_of(&init));
+    }
+}
+
+// Upload the reaction table whenever it is rebuilt
+pub fn prepare_reaction_buffer(
+    render_queue: Res<RenderQueue>,
+    buffers: Res<GameOfLifeBuffers>,
+    reactions: Option<Res<ReactionTable>>,
+) {
+    let Some(reactions) = reactions.filter(|reactions| reactions.is_changed()) else {
+        return;
+    };
+    render_queue.write_buffer(
+        &buffers.reactions,
+        0,
+        bytemuck::cast_slice(reactions.to_gpu()),
+    );
+}
 
 pub fn prepare_automata_bind_group(
     mut commands: Commands,
     render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
 
     params: Res<AutomataParams>,
     pipeline: Res<GameOfLifePipeline>,
     buffers: Res<GameOfLifeBuffers>,
 ) {
-    // Swap (ping pong) buffers between input and output every frame
+    // The update shader uses the step number and the seed to vary its random choices. The steps
+    // after the first one this frame get theirs from `step_frames`, see `GameOfLifeNode::run`.
     let frame = params.frame.load(Ordering::SeqCst);
-    let (buffer_in, buffer_out) = if frame % 2 == 0 {
-        (&buffers.in_out[0], &buffers.in_out[1])
-    } else {
-        (&buffers.in_out[1], &buffers.in_out[0])
-    };
+    let step_frames: [u32; MAX_STEPS_PER_FRAME] = std::array::from_fn(|step| (frame + step) as u32);
+    render_queue.write_buffer(&buffers.frame, 0, bytemuck::bytes_of(&(frame as u32)));
+    render_queue.write_buffer(&buffers.step_frames, 0, bytemuck::cast_slice(&step_frames));
+    render_queue.write_buffer(&buffers.seed, 0, bytemuck::bytes_of(&params.seed.0));
 
-    let bind_group = render_device.create_bind_group(
-        "Automata Bind Group 0",
-        &pipeline.texture_bind_group_layout,
+    // Swap (ping pong) buffers between input and output every step
+    let bind_groups = [0, 1].map(|parity| {
+        render_device.create_bind_group(
+            "Automata Bind Group 0",
+            &pipeline.texture_bind_group_layout,
+            &BindGroupEntries::sequential((
+                buffers.size.as_entire_binding(),
+                buffers.in_out[parity].as_entire_binding(),
+                buffers.in_out[1 - parity].as_entire_binding(),
+                buffers.materials.as_entire_binding(),
+                buffers.init.as_entire_binding(),
+                buffers.frame.as_entire_binding(),
+                buffers.intents.as_entire_binding(),
+                buffers.temperatures.as_entire_binding(),
+                buffers.reactions.as_entire_binding(),
+                buffers.products.as_entire_binding(),
+                buffers.tile_activity.as_entire_binding(),
+                buffers.awake_chunks.as_entire_binding(),
+                buffers.tile_awake.as_entire_binding(),
+                buffers.tile_colors.as_entire_binding(),
+                buffers.seed.as_entire_binding(),
+            )),
+        )
+    });
+    commands.insert_resource(GameOfLifeImageBindGroups(bind_groups));
+
+    let chunk_bind_group = render_device.create_bind_group(
+        "Chunks Bind Group 0",
+        &pipeline.chunk_bind_group_layout,
         &BindGroupEntries::sequential((
             buffers.size.as_entire_binding(),
-            buffer_in.as_entire_binding(),
-            buffer_out.as_entire_binding(),
+            buffers.tile_activity.as_entire_binding(),
+            buffers.tile_quiet.as_entire_binding(),
+            buffers.tile_awake.as_entire_binding(),
+            buffers.awake_chunks.as_entire_binding(),
+            buffers.dispatch.as_entire_binding(),
+            buffers.tile_colors.as_entire_binding(),
+            buffers.color_chunks.as_entire_binding(),
+            buffers.color_dispatch.as_entire_binding(),
+            buffers.tile_stats.as_entire_binding(),
         )),
     );
-    commands.insert_resource(GameOfLifeImageBindGroup(bind_group));
+    commands.insert_resource(GameOfLifeChunkBindGroup(chunk_bind_group));
 }
 
 enum GameOfLifeState {
@@ -154,12 +763,21 @@ enum GameOfLifeState {
 
 pub struct GameOfLifeNode {
     state: GameOfLifeState,
+    /// Step number before the steps that `run` takes this frame.
+    frame: usize,
+    /// How many steps `run` takes this frame.
+    steps: usize,
+    /// Resets asked for so far, see `reset`.
+    resets: usize,
 }
 
 impl Default for GameOfLifeNode {
     fn default() -> Self {
         Self {
             state: GameOfLifeState::Loading,
+            frame: 0,
+            steps: 0,
+            resets: 0,
         }
     }
 }
@@ -173,9 +791,15 @@ impl render_graph::Node for GameOfLifeNode {
         match self.state {
             GameOfLifeState::Loading => {
                 match pipeline_cache.get_compute_pipeline_state(pipeline.init_pipeline) {
-                    CachedPipelineState::Ok(_) => {
+                    // init colors cells from the material table, so wait for it as well
+                    CachedPipelineState::Ok(_) if world.contains_resource::<MaterialRegistry>() => {
                         self.state = GameOfLifeState::Init;
                     }
+                    // Retried by the pipeline cache once the shader and its imports have loaded
+                    CachedPipelineState::Err(
+                        PipelineCacheError::ShaderNotLoaded(_)
+                        | PipelineCacheError::ShaderImportNotYetAvailable,
+                    ) => {}
                     CachedPipelineState::Err(err) => {
                         panic!("Initializing assets/{SHADER_ASSET_PATH}:\n{err}")
                     }
@@ -183,20 +807,63 @@ impl render_graph::Node for GameOfLifeNode {
                 }
             }
             GameOfLifeState::Init => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline)
-                {
+                let is_ready = [
+                    pipeline.settle_pipeline,
+                    pipeline.plan_tiles_pipeline,
+                    pipeline.plan_chunks_pipeline,
+                    pipeline.heat_pipeline,
+                    pipeline.react_pipeline,
+                    pipeline.claim_pipeline,
+                    pipeline.update_pipeline,
+                ]
+                .into_iter()
+                .all(|id| {
+                    matches!(
+                        pipeline_cache.get_compute_pipeline_state(id),
+                        CachedPipelineState::Ok(_)
+                    )
+                });
+                if is_ready {
                     self.state = GameOfLifeState::Update;
                 }
             }
-            GameOfLifeState::Update => {
-                let params = world.resource_mut::<AutomataParams>();
+            GameOfLifeState::Update => {}
+        }
 
-                if params.steps_left.load(Ordering::SeqCst) > 0 {
-                    params.frame.fetch_add(1, Ordering::SeqCst);
-                }
+        // Filled again from the start when the world is reset, and stepped on from the next frame
+        let resets = world
+            .get_resource::<WorldResets>()
+            .map_or(0, |resets| resets.count);
+        if resets != self.resets {
+            self.resets = resets;
+            if let GameOfLifeState::Update = self.state {
+                self.state = GameOfLifeState::Init;
             }
         }
+
+        // Count the steps that `run` is about to take, including the first one. A replay holds
+        // them back from the first one that strokes were recorded before, until those have been
+        // painted, see `replay`. Only the last step of a frame is colored, so a stream of images
+        // gets one step per frame, see `readback`.
+        let params = world.resource::<AutomataParams>();
+        self.frame = params.frame.load(Ordering::SeqCst);
+        self.steps = 0;
+        if let GameOfLifeState::Update = self.state {
+            let replay = world.get_resource::<ReplayInputs>();
+            let max_steps = if world
+                .get_resource::<Readback>()
+                .is_some_and(Readback::are_images_streamed)
+            {
+                1
+            } else {
+                MAX_STEPS_PER_FRAME
+            };
+            let steps = params.steps_left.load(Ordering::SeqCst).min(max_steps);
+            self.steps = (0..steps)
+                .find(|step| replay.is_some_and(|replay| replay.holds_step(self.frame + step)))
+                .unwrap_or(steps);
+            params.frame.fetch_add(self.steps, Ordering::SeqCst);
+        }
     }
     fn run(
         &self,
@@ -206,45 +873,119 @@ impl render_graph::Node for GameOfLifeNode {
     ) -> Result<(), render_graph::NodeRunError> {
         let params = &world.resource::<AutomataParams>();
 
-        let is_paused = params.is_paused;
-
-        if is_paused && (params.steps_left.load(Ordering::SeqCst) == 0) {
-            return Ok(());
+        // The grid is initialized even while paused, and steps asked for before then are kept
+        match self.state {
+            GameOfLifeState::Loading => return Ok(()),
+            GameOfLifeState::Init => {}
+            // Stepping again without a step being due would only redo the last one, and count
+            // towards putting tiles to sleep
+            GameOfLifeState::Update => {
+                if self.steps == 0 {
+                    return Ok(());
+                }
+            }
         }
 
-        let automata_bind_group = &world.resource::<GameOfLifeImageBindGroup>().0;
+        let automata_bind_groups = &world.resource::<GameOfLifeImageBindGroups>().0;
+        let chunk_bind_group = &world.resource::<GameOfLifeChunkBindGroup>().0;
         let pipeline_cache = world.resource::<PipelineCache>();
         let pipeline = world.resource::<GameOfLifePipeline>();
-
-        let mut pass = render_context
-            .command_encoder()
-            .begin_compute_pass(&ComputePassDescriptor::default());
-
-        pass.set_bind_group(0, automata_bind_group, &[]);
+        let config = world.resource::<GridConfig>();
+        let workgroups = config.workgroups();
+        let buffers = world.resource::<GameOfLifeBuffers>();
+        let rewind = world.get_resource::<RewindBuffers>();
 
         // select the pipeline based on the current state
         match self.state {
             GameOfLifeState::Loading => {}
+            // A loaded snapshot has already been uploaded, see `snapshot::prepare_loaded_snapshot`
+            GameOfLifeState::Init if world.contains_resource::<LoadedSnapshot>() => {}
             GameOfLifeState::Init => {
                 let init_pipeline = pipeline_cache
                     .get_compute_pipeline(pipeline.init_pipeline)
                     .unwrap();
 
+                let mut pass = render_context
+                    .command_encoder()
+                    .begin_compute_pass(&ComputePassDescriptor::default());
+                pass.set_bind_group(0, &automata_bind_groups[self.frame % 2], &[]);
                 pass.set_pipeline(init_pipeline);
-                pass.dispatch_workgroups(SIZE.0 / WORKGROUP_SIZE, SIZE.1 / WORKGROUP_SIZE, 1);
+                pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
             }
             GameOfLifeState::Update => {
+                let settle_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.settle_pipeline)
+                    .unwrap();
+                let plan_tiles_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.plan_tiles_pipeline)
+                    .unwrap();
+                let plan_chunks_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.plan_chunks_pipeline)
+                    .unwrap();
+                let heat_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.heat_pipeline)
+                    .unwrap();
+                let react_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.react_pipeline)
+                    .unwrap();
+                let claim_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.claim_pipeline)
+                    .unwrap();
                 let update_pipeline = pipeline_cache
                     .get_compute_pipeline(pipeline.update_pipeline)
                     .unwrap();
-                pass.set_pipeline(update_pipeline);
-                pass.dispatch_workgroups(SIZE.0 / WORKGROUP_SIZE, SIZE.1 / WORKGROUP_SIZE, 1);
+                let tile_workgroups = config.tile_workgroups();
+                let chunk_workgroups = config.chunk_workgroups();
+                let dispatch = &buffers.dispatch;
+
+                for step in 0..self.steps {
+                    let frame_size = std::mem::size_of::<u32>() as u64;
+                    let encoder = render_context.command_encoder();
+                    encoder.copy_buffer_to_buffer(
+                        &buffers.step_frames,
+                        step as u64 * frame_size,
+                        &buffers.frame,
+                        0,
+                        frame_size,
+                    );
+                    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+
+                    // Pick the tiles to step and the chunks they are in, see `chunks.wgsl`
+                    pass.set_bind_group(0, chunk_bind_group, &[]);
+                    pass.set_pipeline(settle_pipeline);
+                    pass.dispatch_workgroups(tile_workgroups.x, tile_workgroups.y, 1);
+                    pass.set_pipeline(plan_tiles_pipeline);
+                    pass.dispatch_workgroups(tile_workgroups.x, tile_workgroups.y, 1);
+                    pass.set_pipeline(plan_chunks_pipeline);
+                    pass.dispatch_workgroups(chunk_workgroups.x, chunk_workgroups.y, 1);
+
+                    pass.set_bind_group(0, &automata_bind_groups[(self.frame + step) % 2], &[]);
+                    pass.set_pipeline(heat_pipeline);
+                    pass.dispatch_workgroups_indirect(dispatch, 0);
+                    pass.set_pipeline(react_pipeline);
+                    pass.dispatch_workgroups_indirect(dispatch, 0);
+                    pass.set_pipeline(claim_pipeline);
+                    pass.dispatch_workgroups_indirect(dispatch, 0);
+                    pass.set_pipeline(update_pipeline);
+                    pass.dispatch_workgroups_indirect(dispatch, 0);
+                    drop(pass);
+
+                    if let Some(rewind) = rewind {
+                        let frame = self.frame + step + 1;
+                        rewind.keep(encoder, &buffers.in_out[frame % 2], frame);
+                    }
+                }
+
+                params.steps_left.fetch_sub(self.steps, Ordering::SeqCst);
             }
         }
 
-        if params.steps_left.load(Ordering::SeqCst) > 0 {
-            params.steps_left.fetch_sub(1, Ordering::SeqCst);
+        // The grid it starts from can be gone back to as well
+        if let (GameOfLifeState::Init, Some(rewind)) = (&self.state, rewind) {
+            let encoder = render_context.command_encoder();
+            rewind.keep(encoder, &buffers.in_out[self.frame % 2], self.frame);
         }
+
         Ok(())
     }
 }
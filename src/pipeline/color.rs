@@ -9,12 +9,31 @@ use bevy::{
         Render, RenderSet,
     },
 };
+use bytemuck::{Pod, Zeroable};
 use std::{borrow::Cow, sync::atomic::Ordering};
 
 use super::automata::{
-    GameOfLifeBuffers, GameOfLifeImage, GameOfLifeImageBindGroup, BIND_GROUP_LAYOUT_ENTRY_CELL,
+    GameOfLifeBuffers, GameOfLifeChunkBindGroup, GameOfLifeImage, GameOfLifePipeline,
+    BIND_GROUP_LAYOUT_ENTRY_CELL, BIND_GROUP_LAYOUT_ENTRY_MATERIALS,
+    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
 };
-use crate::{input::AutomataParams, SIZE, WORKGROUP_SIZE};
+use crate::{chunk::CHUNK_SIZE, input::AutomataParams, utils, GridConfig, WORKGROUP_SIZE};
+
+/// How to color the cells, laid out to match `ColorParams` in `color.wgsl`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Zeroable, Pod)]
+#[repr(C)]
+pub struct ColorUniform {
+    /// Non-zero to show the temperature of each cell instead of its color.
+    pub show_heatmap: u32,
+}
+
+impl From<&AutomataParams> for ColorUniform {
+    fn from(params: &AutomataParams) -> Self {
+        Self {
+            show_heatmap: params.show_heatmap.into(),
+        }
+    }
+}
 
 pub struct AutomataColorPipelinePlugin;
 impl Plugin for AutomataColorPipelinePlugin {
@@ -23,7 +42,10 @@ impl Plugin for AutomataColorPipelinePlugin {
             .init_resource::<AutomataColorPipeline>()
             .add_systems(
                 Render,
-                prepare_color_bind_group.in_set(RenderSet::PrepareBindGroups),
+                (
+                    prepare_color_uniform.in_set(RenderSet::PrepareResources),
+                    prepare_color_bind_group.in_set(RenderSet::PrepareBindGroups),
+                ),
             );
     }
 }
@@ -35,6 +57,7 @@ pub struct AutomataColorLabel;
 pub struct AutomataColorPipeline {
     color_pipeline: CachedComputePipelineId,
     color_bind_group_layout: BindGroupLayout,
+    color_uniform: Buffer,
 }
 
 impl FromWorld for AutomataColorPipeline {
@@ -70,10 +93,40 @@ impl FromWorld for AutomataColorPipeline {
                         },
                         count: None,
                     },
+                    BIND_GROUP_LAYOUT_ENTRY_MATERIALS,
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<ColorUniform>() as _
+                            ),
+                        },
+                    },
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<UVec2>() as _),
+                        },
+                    },
                 ),
             ),
         );
 
+        let color_uniform = utils::create_uniform_buffer(
+            world.resource::<RenderDevice>(),
+            &[ColorUniform::default()],
+            Some("Color Uniform Buffer"),
+        );
+
         let color_shader = world.resource::<AssetServer>().load("shaders/color.wgsl");
 
         let color_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
@@ -88,42 +141,74 @@ impl FromWorld for AutomataColorPipeline {
         AutomataColorPipeline {
             color_pipeline,
             color_bind_group_layout,
+            color_uniform,
         }
     }
 }
 
 // ================================== BindGroup ================================== //
 
+/// One for each of the `in_out` buffers to color from, indexed like them.
 #[derive(Resource)]
-struct AutomataColorBindGroups(pub BindGroup);
+struct AutomataColorBindGroups([BindGroup; 2]);
+
+pub fn prepare_color_uniform(
+    render_queue: Res<RenderQueue>,
+    params: Res<AutomataParams>,
+    pipeline: Res<AutomataColorPipeline>,
+    buffers: Res<GameOfLifeBuffers>,
+    mut last_uniform: Local<ColorUniform>,
+) {
+    let uniform = ColorUniform::from(params.as_ref());
+    render_queue.write_buffer(&pipeline.color_uniform, 0, bytemuck::bytes_of(&uniform));
+    // Cells that haven't changed are to be colored differently as well
+    if uniform != *last_uniform {
+        buffers.recolor(&render_queue);
+        *last_uniform = uniform;
+    }
+
+    // Counted up again by `plan_colors` in `chunks.wgsl`, a layer of workgroups per chunk
+    let tiles_per_chunk = CHUNK_SIZE / WORKGROUP_SIZE;
+    render_queue.write_buffer(
+        &buffers.color_dispatch,
+        0,
+        bytemuck::cast_slice(&[tiles_per_chunk, tiles_per_chunk, 0]),
+    );
+    // along with the tiles colored, after the ones stepped
+    render_queue.write_buffer(
+        &buffers.tile_stats,
+        std::mem::size_of::<u32>() as u64,
+        bytemuck::bytes_of(&0u32),
+    );
+}
 
 pub fn prepare_color_bind_group(
     mut commands: Commands,
     render_device: Res<RenderDevice>,
     buffers: Res<GameOfLifeBuffers>,
-    params: Res<AutomataParams>,
     pipeline: Res<AutomataColorPipeline>,
     gpu_images: Res<RenderAssets<GpuImage>>,
     game_of_life_image: Res<GameOfLifeImage>,
 ) {
-    let frame = params.frame.load(Ordering::SeqCst);
-    let (buffer_in, buffer_out) = if frame % 2 == 0 {
-        (&buffers.in_out[0], &buffers.in_out[1])
-    } else {
-        (&buffers.in_out[1], &buffers.in_out[0])
-    };
     let view = gpu_images.get(&game_of_life_image.texture).unwrap();
-    let color_bind_group = render_device.create_bind_group(
-        Some("Game of Life Color Bind Group"),
-        &pipeline.color_bind_group_layout,
-        &BindGroupEntries::sequential((
-            buffers.size.as_entire_binding(),
-            buffer_in.as_entire_binding(),
-            buffer_out.as_entire_binding(),
-            &view.texture_view,
-        )),
-    );
-    commands.insert_resource(AutomataColorBindGroups(color_bind_group));
+    // Whether this frame steps or not is only known once the graph runs, see `AutomataColorNode`
+    let color_bind_groups = [0, 1].map(|i| {
+        render_device.create_bind_group(
+            Some("Game of Life Color Bind Group"),
+            &pipeline.color_bind_group_layout,
+            &BindGroupEntries::sequential((
+                buffers.size.as_entire_binding(),
+                buffers.in_out[i].as_entire_binding(),
+                buffers.in_out[1 - i].as_entire_binding(),
+                &view.texture_view,
+                buffers.materials.as_entire_binding(),
+                pipeline.color_uniform.as_entire_binding(),
+                buffers.tile_colors.as_entire_binding(),
+                buffers.color_chunks.as_entire_binding(),
+            )),
+        )
+    });
+    commands.insert_resource(AutomataColorBindGroups(color_bind_groups));
 }
 
 // ================================== Nodes ================================== //
@@ -149,12 +234,20 @@ impl render_graph::Node for AutomataColorNode {
         let pipeline = world.resource::<AutomataColorPipeline>();
         let pipeline_cache = world.resource::<PipelineCache>();
 
-        // if the corresponding pipeline has loaded, transition to the next stage
+        let plan_pipeline = world.resource::<GameOfLifePipeline>().plan_colors_pipeline;
+
+        // if the corresponding pipelines have loaded, transition to the next stage
         match self.state {
             AutomataColorState::Loading => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.color_pipeline)
-                {
+                let is_ready = [pipeline.color_pipeline, plan_pipeline]
+                    .into_iter()
+                    .all(|id| {
+                        matches!(
+                            pipeline_cache.get_compute_pipeline_state(id),
+                            CachedPipelineState::Ok(_)
+                        )
+                    });
+                if is_ready {
                     self.state = AutomataColorState::Update;
                 }
             }
@@ -168,28 +261,40 @@ impl render_graph::Node for AutomataColorNode {
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), render_graph::NodeRunError> {
-        let bind_group = &world.resource::<GameOfLifeImageBindGroup>().0;
-        let color_bind_group = &world.resource::<AutomataColorBindGroups>().0;
+        let chunk_bind_group = &world.resource::<GameOfLifeChunkBindGroup>().0;
+        let color_bind_groups = &world.resource::<AutomataColorBindGroups>().0;
         let pipeline_cache = world.resource::<PipelineCache>();
         let pipeline = world.resource::<AutomataColorPipeline>();
+        let plan_pipeline = world.resource::<GameOfLifePipeline>().plan_colors_pipeline;
+        let chunk_workgroups = world.resource::<GridConfig>().chunk_workgroups();
+        let color_dispatch = &world.resource::<GameOfLifeBuffers>().color_dispatch;
+        // The cells after this frame's step, if it took one
+        let frame = world
+            .resource::<AutomataParams>()
+            .frame
+            .load(Ordering::SeqCst);
 
         let mut pass = render_context
             .command_encoder()
             .begin_compute_pass(&ComputePassDescriptor::default());
 
-        pass.set_bind_group(0, bind_group, &[]);
-
         // select the pipeline based on the current state
         match self.state {
             AutomataColorState::Loading => {}
             AutomataColorState::Update => {
+                let plan_pipeline = pipeline_cache.get_compute_pipeline(plan_pipeline).unwrap();
                 let color_pipeline = pipeline_cache
                     .get_compute_pipeline(pipeline.color_pipeline)
                     .unwrap();
 
+                // Pick the chunks with tiles to color, see `chunks.wgsl`
+                pass.set_pipeline(plan_pipeline);
+                pass.set_bind_group(0, chunk_bind_group, &[]);
+                pass.dispatch_workgroups(chunk_workgroups.x, chunk_workgroups.y, 1);
+
                 pass.set_pipeline(color_pipeline);
-                pass.set_bind_group(0, color_bind_group, &[]);
-                pass.dispatch_workgroups(SIZE.0 / WORKGROUP_SIZE, SIZE.1 / WORKGROUP_SIZE, 1);
+                pass.set_bind_group(0, &color_bind_groups[frame % 2], &[]);
+                pass.dispatch_workgroups_indirect(color_dispatch, 0);
             }
         }
 
@@ -0,0 +1,257 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_graph::{self, RenderLabel},
+        render_resource::*,
+        renderer::*,
+        Render, RenderSet,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+use std::{borrow::Cow, sync::atomic::Ordering};
+
+use super::automata::{
+    GameOfLifeBuffers, BIND_GROUP_LAYOUT_ENTRY_CELL, BIND_GROUP_LAYOUT_ENTRY_MATERIALS,
+    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+};
+use crate::{input::AutomataParams, utils, GridConfig};
+
+const SHADER_ASSET_PATH: &str = "shaders/draw.wgsl";
+
+/// Brush stroke for the current frame, laid out to match `DrawParams` in `draw.wgsl`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Zeroable, Pod)]
+#[repr(C)]
+pub struct DrawUniform {
+    pub start: [f32; 2],
+    pub end: [f32; 2],
+    pub radius: f32,
+    pub type_id: i32,
+}
+
+impl From<&AutomataParams> for DrawUniform {
+    fn from(params: &AutomataParams) -> Self {
+        Self {
+            start: params.prev_mouse_pos.to_array(),
+            end: params.mouse_pos.to_array(),
+            radius: params.brush_radius,
+            type_id: params.brush_type_id,
+        }
+    }
+}
+
+pub struct AutomataDrawPipelinePlugin;
+impl Plugin for AutomataDrawPipelinePlugin {
+    fn build(&self, render_app: &mut App) {
+        render_app
+            .init_resource::<AutomataDrawPipeline>()
+            .add_systems(
+                Render,
+                prepare_draw_bind_group.in_set(RenderSet::PrepareBindGroups),
+            );
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct AutomataDrawLabel;
+
+#[derive(Resource)]
+pub struct AutomataDrawPipeline {
+    draw_pipeline: CachedComputePipelineId,
+    draw_bind_group_layout: BindGroupLayout,
+    draw_uniform: Buffer,
+}
+
+impl FromWorld for AutomataDrawPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let draw_bind_group_layout = render_device.create_bind_group_layout(
+            Some("Automata Draw Bind Group Layout"),
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                (2 * std::mem::size_of::<u32>()) as _,
+                            ),
+                        },
+                    },
+                    BindGroupLayoutEntry {
+                        binding: u32::MAX,
+                        count: None,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<DrawUniform>() as _
+                            ),
+                        },
+                    },
+                    BIND_GROUP_LAYOUT_ENTRY_CELL,
+                    BIND_GROUP_LAYOUT_ENTRY_MATERIALS,
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                    BIND_GROUP_LAYOUT_ENTRY_TILE_FLAGS,
+                ),
+            ),
+        );
+
+        let draw_uniform = utils::create_uniform_buffer(
+            render_device,
+            &[DrawUniform::default()],
+            Some("Draw Uniform Buffer"),
+        );
+
+        let draw_shader = world.load_asset(SHADER_ASSET_PATH);
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let draw_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            shader: draw_shader,
+            shader_defs: vec![],
+            push_constant_ranges: vec![],
+            entry_point: Cow::from("draw_cells"),
+            layout: vec![draw_bind_group_layout.clone()],
+            label: Some(Cow::Borrowed("Automata Draw Pipeline")),
+        });
+
+        AutomataDrawPipeline {
+            draw_pipeline,
+            draw_bind_group_layout,
+            draw_uniform,
+        }
+    }
+}
+
+impl AutomataDrawPipeline {
+    /// Whether the draw node paints this frame's stroke, if any.
+    pub(crate) fn is_ready(&self, pipeline_cache: &PipelineCache) -> bool {
+        pipeline_cache
+            .get_compute_pipeline(self.draw_pipeline)
+            .is_some()
+    }
+}
+
+// ================================== BindGroup ================================== //
+
+#[derive(Resource)]
+struct AutomataDrawBindGroup(pub BindGroup);
+
+pub fn prepare_draw_bind_group(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    buffers: Res<GameOfLifeBuffers>,
+    params: Res<AutomataParams>,
+    pipeline: Res<AutomataDrawPipeline>,
+) {
+    render_queue.write_buffer(
+        &pipeline.draw_uniform,
+        0,
+        bytemuck::bytes_of(&DrawUniform::from(params.as_ref())),
+    );
+
+    // Paint into the buffer that the next update reads from
+    let frame = params.frame.load(Ordering::SeqCst);
+    let buffer_in = &buffers.in_out[frame % 2];
+
+    let bind_group = render_device.create_bind_group(
+        Some("Automata Draw Bind Group"),
+        &pipeline.draw_bind_group_layout,
+        &BindGroupEntries::sequential((
+            buffers.size.as_entire_binding(),
+            pipeline.draw_uniform.as_entire_binding(),
+            buffer_in.as_entire_binding(),
+            buffers.materials.as_entire_binding(),
+            buffers.tile_activity.as_entire_binding(),
+            buffers.tile_colors.as_entire_binding(),
+        )),
+    );
+    commands.insert_resource(AutomataDrawBindGroup(bind_group));
+}
+
+// ================================== Nodes ================================== //
+pub enum AutomataDrawState {
+    Loading,
+    Update,
+}
+
+pub struct AutomataDrawNode {
+    state: AutomataDrawState,
+}
+
+impl Default for AutomataDrawNode {
+    fn default() -> Self {
+        Self {
+            state: AutomataDrawState::Loading,
+        }
+    }
+}
+
+impl render_graph::Node for AutomataDrawNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<AutomataDrawPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        // if the corresponding pipeline has loaded, transition to the next stage
+        match self.state {
+            AutomataDrawState::Loading => {
+                match pipeline_cache.get_compute_pipeline_state(pipeline.draw_pipeline) {
+                    CachedPipelineState::Ok(_) => {
+                        self.state = AutomataDrawState::Update;
+                    }
+                    // Retried by the pipeline cache once the shader and its imports have loaded
+                    CachedPipelineState::Err(
+                        PipelineCacheError::ShaderNotLoaded(_)
+                        | PipelineCacheError::ShaderImportNotYetAvailable,
+                    ) => {}
+                    CachedPipelineState::Err(err) => {
+                        panic!("Initializing assets/{SHADER_ASSET_PATH}:\n{err}")
+                    }
+                    _ => {}
+                }
+            }
+            AutomataDrawState::Update => {}
+        }
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        // Painting still applies while the simulation is paused
+        if !world.resource::<AutomataParams>().is_drawing {
+            return Ok(());
+        }
+
+        let draw_bind_group = &world.resource::<AutomataDrawBindGroup>().0;
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<AutomataDrawPipeline>();
+        let workgroups = world.resource::<GridConfig>().workgroups();
+
+        match self.state {
+            AutomataDrawState::Loading => {}
+            AutomataDrawState::Update => {
+                let draw_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.draw_pipeline)
+                    .unwrap();
+
+                let mut pass = render_context
+                    .command_encoder()
+                    .begin_compute_pass(&ComputePassDescriptor::default());
+
+                pass.set_pipeline(draw_pipeline);
+                pass.set_bind_group(0, draw_bind_group, &[]);
+                pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,2 +1,3 @@
 pub mod automata;
 pub mod color;
+pub mod draw;
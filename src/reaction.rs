@@ -0,0 +1,247 @@
+//! Data-driven reactions between neighboring cells.
+//!
+//! Reactions are described in `assets/litterbox.reactions.ron` in terms of material names, and
+//! resolved against the [`MaterialRegistry`] into a [`ReactionTable`] that the `react` entry
+//! point of `litterbox.wgsl` looks up by pair of `type_id`s. Fire spreading to flammable materials
+//! doesn't need to be listed: a material's `flammability` and `burns_into` stand for
+//! `burns_into + material -> burns_into + burns_into`, with that chance, unless the pair is
+//! listed.
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    render::extract_resource::ExtractResource,
+};
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::material::{MaterialRegistry, MAX_MATERIALS};
+
+const REACTIONS_ASSET_PATH: &str = "litterbox.reactions.ron";
+
+/// When a cell of material `reactant` touches a cell of material `neighbor`, both may turn
+/// into something else: `Water + Lava -> Steam + Stone`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReactionDescriptor {
+    pub reactant: String,
+    pub neighbor: String,
+    /// Chance in `[0, 1]` per step that two touching cells react.
+    pub chance: f32,
+    /// Name of the material the reactant turns into, or `None` for it to stay as it is.
+    #[serde(default)]
+    pub into: Option<String>,
+    /// Name of the material the neighbor turns into, or `None` for it to stay as it is.
+    /// Ignored when a material reacts with itself, both cells then turn into `into`.
+    #[serde(default)]
+    pub neighbor_into: Option<String>,
+}
+
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct ReactionList {
+    pub reactions: Vec<ReactionDescriptor>,
+}
+
+impl ReactionList {
+    /// Parses a reaction list in the format of `assets/litterbox.reactions.ron`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReactionTableError> {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReactionTableError {
+    #[error("could not read reactions: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse reactions: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("reaction `{0} + {1}` refers to `{2}`, which is not in the material table")]
+    UnknownMaterial(String, String, String),
+    #[error("reaction `{0} + {1}` has a chance of {2}, it must be in [0, 1]")]
+    ChanceOutOfRange(String, String, f32),
+    #[error("reaction `{0} + {1}` is listed more than once, in either order")]
+    Duplicate(String, String),
+}
+
+#[derive(Default)]
+pub struct ReactionListLoader;
+
+impl AssetLoader for ReactionListLoader {
+    type Asset = ReactionList;
+    type Settings = ();
+    type Error = ReactionTableError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<ReactionList, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        ReactionList::from_bytes(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["reactions.ron"]
+    }
+}
+
+/// One side of a reaction as seen by the shaders, see `Reaction` in `litterbox.wgsl`.
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct GpuReaction {
+    /// 0 if the pair doesn't react.
+    pub chance: f32,
+    /// `type_id` to turn into, -1 to stay the same.
+    pub into: i32,
+}
+
+impl Default for GpuReaction {
+    fn default() -> Self {
+        Self {
+            chance: 0.,
+            into: -1,
+        }
+    }
+}
+
+/// Every reaction from the point of view of each cell taking part in it, indexed by
+/// `type_id * MAX_MATERIALS + neighbor_type_id`. Rebuilt whenever either the reactions or the
+/// materials they refer to change.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct ReactionTable {
+    reactions: Vec<GpuReaction>,
+}
+
+impl ReactionTable {
+    pub fn new(
+        list: &ReactionList,
+        registry: &MaterialRegistry,
+    ) -> Result<Self, ReactionTableError> {
+        let mut reactions = vec![GpuReaction::default(); MAX_MATERIALS * MAX_MATERIALS];
+        // Whether a pair is listed, whatever its chance, 0 included
+        let mut listed = vec![false; MAX_MATERIALS * MAX_MATERIALS];
+        for reaction in &list.reactions {
+            let id = |name: &str| {
+                registry.id(name).ok_or_else(|| {
+                    ReactionTableError::UnknownMaterial(
+                        reaction.reactant.clone(),
+                        reaction.neighbor.clone(),
+                        name.to_owned(),
+                    )
+                })
+            };
+            let reactant = id(&reaction.reactant)?;
+            let neighbor = id(&reaction.neighbor)?;
+            let into = reaction.into.as_deref().map_or(Ok(-1), id)?;
+            let neighbor_into = reaction.neighbor_into.as_deref().map_or(Ok(-1), id)?;
+            if !(0. ..=1.).contains(&reaction.chance) {
+                return Err(ReactionTableError::ChanceOutOfRange(
+                    reaction.reactant.clone(),
+                    reaction.neighbor.clone(),
+                    reaction.chance,
+                ));
+            }
+
+            let forward = Self::index(reactant, neighbor);
+            let backward = Self::index(neighbor, reactant);
+            if listed[forward] || listed[backward] {
+                return Err(ReactionTableError::Duplicate(
+                    reaction.reactant.clone(),
+                    reaction.neighbor.clone(),
+                ));
+            }
+            listed[forward] = true;
+            listed[backward] = true;
+            reactions[backward] = GpuReaction {
+                chance: reaction.chance,
+                into: neighbor_into,
+            };
+            // Written last, so that both cells take `into` when a material reacts with itself
+            reactions[forward] = GpuReaction {
+                chance: reaction.chance,
+                into,
+            };
+        }
+
+        for type_id in 0..registry.len() as i32 {
+            let Some(fire) = registry.burns_into(type_id) else {
+                continue;
+            };
+            let forward = Self::index(fire, type_id);
+            if listed[forward] || type_id == fire {
+                continue;
+            }
+            let flammability = registry.flammability(type_id);
+            reactions[forward] = GpuReaction {
+                chance: flammability,
+                into: -1,
+            };
+            reactions[Self::index(type_id, fire)] = GpuReaction {
+                chance: flammability,
+                into: fire,
+            };
+        }
+        Ok(Self { reactions })
+    }
+
+    fn index(type_id: i32, neighbor: i32) -> usize {
+        type_id as usize * MAX_MATERIALS + neighbor as usize
+    }
+
+    /// How a cell of material `type_id` reacts to a neighbor of material `neighbor`.
+    pub fn get(&self, type_id: i32, neighbor: i32) -> &GpuReaction {
+        &self.reactions[Self::index(type_id, neighbor)]
+    }
+
+    /// The table to upload into `GameOfLifeBuffers::reactions`.
+    pub fn to_gpu(&self) -> &[GpuReaction] {
+        &self.reactions
+    }
+}
+
+#[derive(Resource)]
+struct ReactionListHandle(Handle<ReactionList>);
+
+pub struct ReactionPlugin;
+impl Plugin for ReactionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ReactionList>()
+            .init_asset_loader::<ReactionListLoader>()
+            .add_systems(Startup, load_reaction_list)
+            .add_systems(Update, update_reaction_table);
+    }
+}
+
+fn load_reaction_list(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ReactionListHandle(asset_server.load(REACTIONS_ASSET_PATH)));
+}
+
+// Reactions refer to materials by name, so they are resolved again whenever either changes
+fn update_reaction_table(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<ReactionList>>,
+    handle: Res<ReactionListHandle>,
+    lists: Res<Assets<ReactionList>>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    let mut list_changed = false;
+    for event in events.read() {
+        list_changed |=
+            event.is_loaded_with_dependencies(&handle.0) || event.is_modified(&handle.0);
+    }
+    let Some(registry) = registry else {
+        return;
+    };
+    if !list_changed && !registry.is_changed() {
+        return;
+    }
+    let Some(list) = lists.get(&handle.0) else {
+        return;
+    };
+    match ReactionTable::new(list, &registry) {
+        Ok(table) => commands.insert_resource(table),
+        Err(err) => error!("assets/{REACTIONS_ASSET_PATH}: {err}"),
+    }
+}
@@ -0,0 +1,307 @@
+//! Copies the cells, or the image they are colored into, from the GPU back into the main world on
+//! request.
+//!
+//! Send a [`RequestCells`] or [`RequestImage`] event, and a [`CellsReadback`] or
+//! [`ImageReadback`] event follows a frame or two later, once the copy has been mapped. Nothing is
+//! copied or waited on in frames without a request. [`StreamImages`] reads back the image after
+//! every step instead, for as long as it is on, and has `GameOfLifeNode` take no more than one
+//! step per frame so that none of them goes unseen.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, OnceLock,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        texture::GpuImage,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    cell::Cell,
+    input::AutomataParams,
+    pipeline::automata::{GameOfLifeBuffers, GameOfLifeImage},
+    GridConfig,
+};
+
+/// Asks for a copy of the cells as they are after the latest step.
+#[derive(Event, Default)]
+pub struct RequestCells;
+
+/// The cells as they were after step `frame`, row by row from the top.
+#[derive(Event, Clone)]
+pub struct CellsReadback {
+    pub frame: usize,
+    pub size: UVec2,
+    pub cells: Vec<Cell>,
+}
+
+/// Asks for a copy of `GameOfLifeImage` as it is shown this frame.
+#[derive(Event, Default)]
+pub struct RequestImage;
+
+/// Starts or stops reading back `GameOfLifeImage` once after every step, without missing any.
+#[derive(Event)]
+pub struct StreamImages(pub bool);
+
+/// `GameOfLifeImage` as it was shown after step `frame`, as RGBA bytes row by row from the top.
+#[derive(Event, Clone)]
+pub struct ImageReadback {
+    pub frame: usize,
+    pub size: UVec2,
+    pub pixels: Vec<u8>,
+}
+
+/// Shared between the main and the render world, like `AutomataParams::frame`.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub(crate) struct Readback {
+    are_cells_requested: Arc<AtomicBool>,
+    is_image_requested: Arc<AtomicBool>,
+    are_images_streamed: Arc<AtomicBool>,
+    cells: Arc<Mutex<Vec<CellsReadback>>>,
+    images: Arc<Mutex<Vec<ImageReadback>>>,
+}
+
+impl Readback {
+    pub(crate) fn are_images_streamed(&self) -> bool {
+        self.are_images_streamed.load(Ordering::SeqCst)
+    }
+}
+
+enum ReadbackSource {
+    Cells,
+    /// Rows of a texture copy are padded to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+    Image {
+        padded_bytes_per_row: usize,
+    },
+}
+
+/// A copy on its way from the GPU.
+struct PendingReadback {
+    source: ReadbackSource,
+    frame: usize,
+    size: UVec2,
+    staging: Buffer,
+    is_mapped: Arc<OnceLock<Result<(), BufferAsyncError>>>,
+}
+
+#[derive(Resource, Default)]
+struct PendingReadbacks {
+    readbacks: Vec<PendingReadback>,
+    /// So that a stream reads back every step once, however many frames it is shown for.
+    last_streamed_frame: Option<usize>,
+}
+
+pub struct ReadbackPlugin;
+impl Plugin for ReadbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RequestCells>()
+            .add_event::<CellsReadback>()
+            .add_event::<RequestImage>()
+            .add_event::<StreamImages>()
+            .add_event::<ImageReadback>()
+            .init_resource::<Readback>()
+            .add_plugins(ExtractResourcePlugin::<Readback>::default())
+            .add_systems(Update, (request_readbacks, send_readbacks));
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<PendingReadbacks>()
+            .add_systems(
+                Render,
+                // After the graph has run, so that this frame's step is included
+                (receive_readbacks, copy_cells, copy_image)
+                    .chain()
+                    .in_set(RenderSet::Cleanup),
+            );
+    }
+}
+
+fn request_readbacks(
+    mut cell_requests: EventReader<RequestCells>,
+    mut image_requests: EventReader<RequestImage>,
+    mut image_streams: EventReader<StreamImages>,
+    readback: Res<Readback>,
+) {
+    if cell_requests.read().count() > 0 {
+        readback.are_cells_requested.store(true, Ordering::SeqCst);
+    }
+    if image_requests.read().count() > 0 {
+        readback.is_image_requested.store(true, Ordering::SeqCst);
+    }
+    if let Some(StreamImages(is_on)) = image_streams.read().last() {
+        readback.are_images_streamed.store(*is_on, Ordering::SeqCst);
+    }
+}
+
+fn send_readbacks(
+    mut cells: EventWriter<CellsReadback>,
+    mut images: EventWriter<ImageReadback>,
+    readback: Res<Readback>,
+) {
+    cells.send_batch(readback.cells.lock().unwrap().drain(..));
+    images.send_batch(readback.images.lock().unwrap().drain(..));
+}
+
+// Copy the buffer that the last step wrote into a staging buffer that can be mapped
+fn copy_cells(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    buffers: Option<Res<GameOfLifeBuffers>>,
+    params: Res<AutomataParams>,
+    config: Res<GridConfig>,
+    readback: Res<Readback>,
+    mut pending: ResMut<PendingReadbacks>,
+) {
+    let Some(buffers) = buffers else {
+        return;
+    };
+    if !readback.are_cells_requested.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    let frame = params.frame.load(Ordering::SeqCst);
+    let buffer = &buffers.in_out[frame % 2];
+    let staging = create_staging_buffer(&render_device, buffer.size());
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    render_queue.submit([encoder.finish()]);
+
+    pending.readbacks.push(PendingReadback {
+        source: ReadbackSource::Cells,
+        frame,
+        size: config.size,
+        is_mapped: map_staging_buffer(&render_device, &staging),
+        staging,
+    });
+}
+
+fn copy_image(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    image: Option<Res<GameOfLifeImage>>,
+    params: Res<AutomataParams>,
+    readback: Res<Readback>,
+    mut pending: ResMut<PendingReadbacks>,
+) {
+    let Some(gpu_image) = image.and_then(|image| gpu_images.get(&image.texture)) else {
+        return;
+    };
+    let frame = params.frame.load(Ordering::SeqCst);
+    let is_streamed = readback.are_images_streamed.load(Ordering::SeqCst)
+        && pending.last_streamed_frame != Some(frame);
+    if !readback.is_image_requested.swap(false, Ordering::SeqCst) && !is_streamed {
+        return;
+    }
+    if is_streamed {
+        pending.last_streamed_frame = Some(frame);
+    }
+
+    let size = gpu_image.texture.size();
+    let padded_bytes_per_row = RenderDevice::align_copy_bytes_per_row(size.width as usize * 4);
+    let staging = create_staging_buffer(
+        &render_device,
+        (padded_bytes_per_row * size.height as usize) as u64,
+    );
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        gpu_image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &staging,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row as u32),
+                rows_per_image: None,
+            },
+        },
+        size,
+    );
+    render_queue.submit([encoder.finish()]);
+
+    pending.readbacks.push(PendingReadback {
+        source: ReadbackSource::Image {
+            padded_bytes_per_row,
+        },
+        frame,
+        size: UVec2::new(size.width, size.height),
+        is_mapped: map_staging_buffer(&render_device, &staging),
+        staging,
+    });
+}
+
+pub(crate) fn create_staging_buffer(render_device: &RenderDevice, size: u64) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("Readback Staging Buffer"),
+        size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// The callback is called from a later submit or poll, once the copy has finished
+pub(crate) fn map_staging_buffer(
+    render_device: &RenderDevice,
+    staging: &Buffer,
+) -> Arc<OnceLock<Result<(), BufferAsyncError>>> {
+    let is_mapped = Arc::new(OnceLock::new());
+    render_device.map_buffer(&staging.slice(..), MapMode::Read, {
+        let is_mapped = is_mapped.clone();
+        move |result| {
+            let _ = is_mapped.set(result);
+        }
+    });
+    is_mapped
+}
+
+fn receive_readbacks(mut pending: ResMut<PendingReadbacks>, readback: Res<Readback>) {
+    pending.readbacks.retain(|pending| {
+        match pending.is_mapped.get() {
+            None => return true,
+            Some(Ok(())) => {
+                let bytes = pending.staging.slice(..).get_mapped_range();
+                let size = pending.size;
+                match pending.source {
+                    ReadbackSource::Cells => {
+                        readback.cells.lock().unwrap().push(CellsReadback {
+                            frame: pending.frame,
+                            size,
+                            cells: bytemuck::pod_collect_to_vec(&bytes),
+                        });
+                    }
+                    ReadbackSource::Image {
+                        padded_bytes_per_row,
+                    } => {
+                        let pixels = bytes
+                            .chunks(padded_bytes_per_row)
+                            .flat_map(|row| &row[..size.x as usize * 4])
+                            .copied()
+                            .collect();
+                        readback.images.lock().unwrap().push(ImageReadback {
+                            frame: pending.frame,
+                            size,
+                            pixels,
+                        });
+                    }
+                }
+                drop(bytes);
+                pending.staging.unmap();
+            }
+            Some(Err(err)) => error!(
+                "Could not read back the GPU state of step {}: {err}",
+                pending.frame
+            ),
+        }
+        false
+    });
+}
@@ -0,0 +1,254 @@
+//! Recordings of the grid with one frame per simulation step, however many render frames each
+//! step takes, as an animated PNG or a numbered PNG sequence.
+//!
+//! Only the last step of a render frame is shown, so no more than one is taken per frame while
+//! recording, see [`crate::readback::StreamImages`]. Speeds above the frame rate are recorded
+//! at the frame rate.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use bevy::{prelude::*, tasks::IoTaskPool};
+use thiserror::Error;
+
+use crate::{
+    readback::{ImageReadback, StreamImages},
+    screenshot::screenshot_image,
+    utils, GridConfig,
+};
+
+/// Default for [`StartRecording::max_steps`], about 64 MB of frames kept for an APNG.
+pub const MAX_RECORDING_STEPS: usize = 1000;
+
+/// How fast APNG recordings play back, whatever the speed of the simulation was.
+pub const PLAYBACK_STEPS_PER_SECOND: u16 = 30;
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Png(#[from] png::EncodingError),
+    #[error("{0}")]
+    Image(#[from] image::ImageError),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// A single animated PNG, written once the recording stops.
+    #[default]
+    Apng,
+    /// A directory with one PNG per step, written as the steps come in.
+    PngSequence,
+}
+
+/// Starts recording from the next step on. Ignored while a recording is already running.
+#[derive(Event, Clone, Copy)]
+pub struct StartRecording {
+    pub format: RecordingFormat,
+    /// Scale the frames up by [`GridConfig::display_factor`], to the size the grid has in the
+    /// window.
+    pub upscale: bool,
+    /// Stop on its own after this many steps.
+    pub max_steps: usize,
+}
+
+impl Default for StartRecording {
+    fn default() -> Self {
+        Self {
+            format: default(),
+            upscale: false,
+            max_steps: MAX_RECORDING_STEPS,
+        }
+    }
+}
+
+/// Stops the running recording, if any, and saves it.
+#[derive(Event, Clone, Copy, Default)]
+pub struct StopRecording;
+
+struct Recording {
+    format: RecordingFormat,
+    scale: u32,
+    max_steps: usize,
+    path: PathBuf,
+    /// Only kept for an APNG, PNG sequences are written step by step.
+    frames: Vec<ImageReadback>,
+    steps: usize,
+    last_frame: Option<usize>,
+}
+
+/// The running recording, if any.
+#[derive(Resource, Default)]
+pub struct Recorder(Option<Recording>);
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+pub struct RecordingPlugin;
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StartRecording>()
+            .add_event::<StopRecording>()
+            .init_resource::<Recorder>()
+            .add_systems(
+                Update,
+                (start_recording, record_steps, stop_recording).chain(),
+            );
+    }
+}
+
+/// Encodes readbacks of `GameOfLifeImage` into an endlessly looping APNG, `scale` times their
+/// size, with every readback shown for the same time.
+pub fn write_apng(
+    frames: &[ImageReadback],
+    scale: u32,
+    writer: impl Write,
+) -> Result<(), RecordingError> {
+    let Some(first) = frames.first() else {
+        return Ok(());
+    };
+    let mut encoder = png::Encoder::new(writer, first.size.x * scale, first.size.y * scale);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_frame_delay(1, PLAYBACK_STEPS_PER_SECOND)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(screenshot_image(frame, scale).as_raw())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn start_recording(
+    mut starts: EventReader<StartRecording>,
+    mut streams: EventWriter<StreamImages>,
+    mut recorder: ResMut<Recorder>,
+    config: Res<GridConfig>,
+) {
+    let Some(start) = starts.read().last() else {
+        return;
+    };
+    if recorder.is_recording() {
+        return;
+    }
+    let timestamp = utils::timestamp();
+    let path = match start.format {
+        RecordingFormat::Apng => PathBuf::from(format!("litterbox-{timestamp}-recording.png")),
+        RecordingFormat::PngSequence => {
+            let path = PathBuf::from(format!("litterbox-{timestamp}-recording"));
+            if let Err(err) = fs::create_dir_all(&path) {
+                error!("Could not create {}: {err}", path.display());
+                return;
+            }
+            path
+        }
+    };
+    info!("Recording to {}", path.display());
+    streams.send(StreamImages(true));
+    recorder.0 = Some(Recording {
+        format: start.format,
+        scale: if start.upscale {
+            config.display_factor
+        } else {
+            1
+        },
+        max_steps: start.max_steps,
+        path,
+        frames: Vec::new(),
+        steps: 0,
+        last_frame: None,
+    });
+}
+
+// Screenshots are read back alongside the stream, so a step can come in more than once
+fn record_steps(
+    mut readbacks: EventReader<ImageReadback>,
+    mut stops: EventWriter<StopRecording>,
+    mut recorder: ResMut<Recorder>,
+) {
+    let Some(recording) = &mut recorder.0 else {
+        readbacks.clear();
+        return;
+    };
+    for readback in readbacks.read() {
+        if recording.steps == recording.max_steps {
+            break;
+        }
+        if recording.last_frame == Some(readback.frame) {
+            continue;
+        }
+        recording.last_frame = Some(readback.frame);
+        recording.steps += 1;
+
+        match recording.format {
+            RecordingFormat::Apng => recording.frames.push(readback.clone()),
+            RecordingFormat::PngSequence => {
+                let path = recording
+                    .path
+                    .join(format!("step-{:06}.png", readback.frame));
+                if let Err(err) = screenshot_image(readback, recording.scale).save(&path) {
+                    error!("Could not save {}: {err}", path.display());
+                }
+            }
+        }
+        if recording.steps == recording.max_steps {
+            warn!(
+                "Stopped recording after the maximum of {} steps",
+                recording.max_steps
+            );
+            stops.send(StopRecording);
+        }
+    }
+}
+
+// An APNG is encoded in the background, it can take a few seconds for a long recording
+fn stop_recording(
+    mut stops: EventReader<StopRecording>,
+    mut streams: EventWriter<StreamImages>,
+    mut recorder: ResMut<Recorder>,
+) {
+    if stops.read().count() == 0 {
+        return;
+    }
+    let Some(recording) = recorder.0.take() else {
+        return;
+    };
+    streams.send(StreamImages(false));
+    if recording.steps == 0 {
+        info!("Stopped recording before any step");
+        return;
+    }
+
+    let Recording {
+        format,
+        scale,
+        path,
+        frames,
+        steps,
+        ..
+    } = recording;
+    match format {
+        RecordingFormat::Apng => {
+            IoTaskPool::get()
+                .spawn(async move {
+                    let result = File::create(&path)
+                        .map_err(RecordingError::from)
+                        .and_then(|file| write_apng(&frames, scale, BufWriter::new(file)));
+                    match result {
+                        Ok(()) => info!("Saved {steps} steps to {}", path.display()),
+                        Err(err) => error!("Could not save {}: {err}", path.display()),
+                    }
+                })
+                .detach();
+        }
+        RecordingFormat::PngSequence => info!("Saved {steps} steps to {}", path.display()),
+    }
+}
@@ -0,0 +1,612 @@
+//! Recording what the user does to the grid into `.replay` files, and playing them back to
+//! reproduce a session bit for bit, to file bugs with.
+//!
+//! A replay starts from a [`Snapshot`] of the grid, taken a frame or two after [`RecordReplay`],
+//! and lists the inputs that followed it, each tagged with the step it came before, see
+//! `AutomataParams::frame`. Only the brush strokes and the seed decide what the cells do, so
+//! those are recorded in the render world when they are applied, and played back there at exactly
+//! the same step: a step is held back until every stroke recorded before it has been painted, see
+//! `GameOfLifeNode`. The other inputs (pausing, stepping, the speed, the brush material and size,
+//! the heatmap) are applied as the steps they were recorded at come up, so that the session plays
+//! back at about the same pace and looks the same.
+//!
+//! Brush strokes and seed changes are ignored while a replay plays. Resizing the grid, moving it
+//! over the world, rewinding it or resetting it is not recorded, and throws a replay off.
+//!
+//! A replay file starts with an uncompressed header and the inputs:
+//!
+//! | bytes    | content                          |
+//! |----------|----------------------------------|
+//! | 6        | `REPLAY`                         |
+//! | 4        | format version, see [`VERSION`]  |
+//! | 4        | number of inputs                 |
+//! | 36 each  | the inputs, see [`ReplayEvent`]  |
+//!
+//! followed by the [`Snapshot`] to start from, as in a `.litter` file. All numbers are little
+//! endian.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::PipelineCache,
+        Render, RenderApp, RenderSet,
+    },
+};
+use thiserror::Error;
+
+pub use crate::pipeline::draw::DrawUniform;
+use crate::{
+    input::AutomataParams,
+    material::MaterialRegistry,
+    pipeline::draw::AutomataDrawPipeline,
+    readback::{CellsReadback, RequestCells},
+    snapshot::{self, Snapshot, SnapshotError},
+    speed::SimulationSpeed,
+    GridConfig, SimulationSeed,
+};
+
+const MAGIC: &[u8; 6] = b"REPLAY";
+
+/// Version of the format written by [`Replay::write_to`]. Bump it whenever [`ReplayInput`]
+/// changes.
+pub const VERSION: u32 = 2;
+
+// Bytes of the payload of an input, the largest being a brush stroke
+const PAYLOAD_SIZE: usize = std::mem::size_of::<DrawUniform>();
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("not a .replay file")]
+    NotAReplay,
+    #[error("saved with format version {0}, only version {VERSION} is supported")]
+    UnsupportedVersion(u32),
+    #[error("unknown input kind {0}")]
+    UnknownInput(u32),
+    #[error("input kind {0} holds {1}, which is not a finite number")]
+    NotFinite(u32, f32),
+    #[error("{0}")]
+    Snapshot(#[from] SnapshotError),
+}
+
+/// Something the user did, sent by the input systems as it happens.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub enum ReplayInput {
+    /// A brush stroke, painted before the step.
+    Paint(DrawUniform),
+    /// The seed of the step and the ones after it.
+    Seed(SimulationSeed),
+    Pause(bool),
+    /// A single step asked for while paused.
+    Step,
+    /// The material painted with, by its `type_id`.
+    Material(i32),
+    BrushRadius(f32),
+    Heatmap(bool),
+    /// Steps per second, see [`SimulationSpeed`].
+    Speed(f32),
+}
+
+impl ReplayInput {
+    /// Whether it changes what the cells do, rather than only how the session looks.
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Self::Paint(_) | Self::Seed(_))
+    }
+
+    fn kind(&self) -> u32 {
+        match self {
+            Self::Paint(_) => 0,
+            Self::Seed(_) => 1,
+            Self::Pause(_) => 2,
+            Self::Step => 3,
+            Self::Material(_) => 4,
+            Self::BrushRadius(_) => 5,
+            Self::Heatmap(_) => 6,
+            Self::Speed(_) => 7,
+        }
+    }
+
+    fn payload(&self) -> [u8; PAYLOAD_SIZE] {
+        let mut payload = [0; PAYLOAD_SIZE];
+        let bytes = match self {
+            Self::Paint(stroke) => bytemuck::bytes_of(stroke).to_vec(),
+            Self::Seed(seed) => seed.0.to_le_bytes().to_vec(),
+            Self::Pause(value) | Self::Heatmap(value) => u32::from(*value).to_le_bytes().to_vec(),
+            Self::Step => Vec::new(),
+            Self::Material(type_id) => type_id.to_le_bytes().to_vec(),
+            Self::BrushRadius(value) | Self::Speed(value) => value.to_le_bytes().to_vec(),
+        };
+        payload[..bytes.len()].copy_from_slice(&bytes);
+        payload
+    }
+
+    fn from_payload(kind: u32, payload: [u8; PAYLOAD_SIZE]) -> Result<Self, ReplayError> {
+        let word = [payload[0], payload[1], payload[2], payload[3]];
+        let finite = |value: f32| {
+            if value.is_finite() {
+                Ok(value)
+            } else {
+                Err(ReplayError::NotFinite(kind, value))
+            }
+        };
+        Ok(match kind {
+            0 => {
+                let stroke: DrawUniform = bytemuck::pod_read_unaligned(&payload);
+                let [x0, y0] = stroke.start;
+                let [x1, y1] = stroke.end;
+                for value in [x0, y0, x1, y1, stroke.radius] {
+                    finite(value)?;
+                }
+                Self::Paint(stroke)
+            }
+            1 => Self::Seed(SimulationSeed(u32::from_le_bytes(word))),
+            2 => Self::Pause(u32::from_le_bytes(word) != 0),
+            3 => Self::Step,
+            4 => Self::Material(i32::from_le_bytes(word)),
+            5 => Self::BrushRadius(finite(f32::from_le_bytes(word))?),
+            6 => Self::Heatmap(u32::from_le_bytes(word) != 0),
+            7 => Self::Speed(finite(f32::from_le_bytes(word))?),
+            _ => return Err(ReplayError::UnknownInput(kind)),
+        })
+    }
+}
+
+/// An input and the step it came before, stored as the step number (8 bytes), the kind of input
+/// (4 bytes) and what it was (24 bytes, zero padded).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayEvent {
+    pub frame: u64,
+    pub input: ReplayInput,
+}
+
+/// A recorded session, see the [module docs](self).
+#[derive(Clone)]
+pub struct Replay {
+    pub snapshot: Snapshot,
+    /// From the step of the snapshot on, by step and then in the order they came in.
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    /// Orders `events` by step and starts them at the step of `snapshot`, which gets the seed of
+    /// the last [`ReplayInput::Seed`] before it.
+    pub fn new(mut snapshot: Snapshot, mut events: Vec<ReplayEvent>) -> Self {
+        events.sort_by_key(|event| event.frame);
+        let start = events.partition_point(|event| event.frame < snapshot.frame);
+        for event in events.drain(..start) {
+            if let ReplayInput::Seed(seed) = event.input {
+                snapshot.seed = seed;
+            }
+        }
+        Self { snapshot, events }
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> Result<(), ReplayError> {
+        writer.write_all(MAGIC)?;
+        for value in [VERSION, self.events.len() as u32] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for event in &self.events {
+            writer.write_all(&event.frame.to_le_bytes())?;
+            writer.write_all(&event.input.kind().to_le_bytes())?;
+            writer.write_all(&event.input.payload())?;
+        }
+        self.snapshot.write_to(writer)?;
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self, ReplayError> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let len = u32::from_le_bytes(read_array(&mut reader)?);
+        let events = (0..len)
+            .map(|_| {
+                let frame = u64::from_le_bytes(read_array(&mut reader)?);
+                let kind = u32::from_le_bytes(read_array(&mut reader)?);
+                let input = ReplayInput::from_payload(kind, read_array(&mut reader)?)?;
+                Ok(ReplayEvent { frame, input })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+        let snapshot = Snapshot::read_from(reader)?;
+        Ok(Self { snapshot, events })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Starts recording a replay, to be saved to the path once it stops. Ignored while a replay is
+/// recorded or played.
+#[derive(Event)]
+pub struct RecordReplay(pub PathBuf);
+
+/// Stops the replay being recorded, if any, and saves it.
+#[derive(Event, Default)]
+pub struct StopReplay;
+
+/// Replaces every cell with the snapshot of a replay, and plays back its inputs.
+#[derive(Event)]
+pub struct PlayReplay(pub PathBuf);
+
+struct Recording {
+    path: PathBuf,
+    /// Step at which the recording started, the snapshot is taken at it or after it.
+    frame: u64,
+    snapshot: Option<Snapshot>,
+}
+
+struct Playback {
+    /// The inputs that are not [`ReplayInput::is_exact`], played back in the main world.
+    events: Vec<ReplayEvent>,
+    next: usize,
+}
+
+/// The replay being recorded or played, if any.
+#[derive(Resource, Default)]
+pub struct Replayer {
+    recording: Option<Recording>,
+    playback: Option<Playback>,
+}
+
+impl Replayer {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Whether the snapshot that the recording starts from has been taken.
+    pub fn has_started(&self) -> bool {
+        self.recording
+            .as_ref()
+            .is_some_and(|recording| recording.snapshot.is_some())
+    }
+}
+
+#[derive(Default)]
+struct RecordedInputs {
+    events: Vec<ReplayEvent>,
+    /// Recorded again whenever it changes, starting with the first frame.
+    seed: Option<SimulationSeed>,
+}
+
+/// The inputs that are [`ReplayInput::is_exact`], played back in the render world.
+struct PlaybackInputs {
+    events: Vec<ReplayEvent>,
+    next: AtomicUsize,
+    seed: AtomicU32,
+}
+
+/// Shared between the main and the render world, like `readback::Readback`.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub(crate) struct ReplayInputs {
+    recording: Option<Arc<Mutex<RecordedInputs>>>,
+    playback: Option<Arc<PlaybackInputs>>,
+}
+
+impl ReplayInputs {
+    /// Whether a stroke recorded before step `frame` is still to be painted.
+    pub(crate) fn holds_step(&self, frame: usize) -> bool {
+        self.playback.as_ref().is_some_and(|playback| {
+            playback
+                .events
+                .get(playback.next.load(Ordering::SeqCst))
+                .is_some_and(|event| event.frame <= frame as u64)
+        })
+    }
+}
+
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReplayInput>()
+            .add_event::<RecordReplay>()
+            .add_event::<StopReplay>()
+            .add_event::<PlayReplay>()
+            .init_resource::<Replayer>()
+            .init_resource::<ReplayInputs>()
+            .add_plugins(ExtractResourcePlugin::<ReplayInputs>::default())
+            .add_systems(
+                Update,
+                (
+                    start_replay_recording,
+                    record_inputs,
+                    stop_replay_recording,
+                    play_replays,
+                    play_back_inputs,
+                )
+                    .chain(),
+            );
+
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            (play_back_strokes, record_strokes)
+                .chain()
+                .in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+fn start_replay_recording(
+    mut starts: EventReader<RecordReplay>,
+    mut requests: EventWriter<RequestCells>,
+    mut replayer: ResMut<Replayer>,
+    mut inputs: ResMut<ReplayInputs>,
+    params: Res<AutomataParams>,
+) {
+    let Some(RecordReplay(path)) = starts.read().last() else {
+        return;
+    };
+    if replayer.is_recording() || replayer.is_playing() {
+        return;
+    }
+    info!("Recording a replay to {}", path.display());
+    inputs.recording = Some(default());
+    requests.send(RequestCells);
+    replayer.recording = Some(Recording {
+        path: path.clone(),
+        frame: params.frame.load(Ordering::SeqCst) as u64,
+        snapshot: None,
+    });
+}
+
+// The snapshot is the first readback of the cells from the step the recording started at on
+fn record_inputs(
+    mut actions: EventReader<ReplayInput>,
+    mut readbacks: EventReader<CellsReadback>,
+    mut replayer: ResMut<Replayer>,
+    inputs: Res<ReplayInputs>,
+    params: Res<AutomataParams>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    let (Some(recording), Some(recorded)) = (&mut replayer.recording, &inputs.recording) else {
+        actions.clear();
+        return;
+    };
+    if recording.snapshot.is_none() {
+        if let (Some(readback), Some(registry)) = (
+            readbacks
+                .read()
+                .filter(|readback| readback.frame as u64 >= recording.frame)
+                .last(),
+            registry,
+        ) {
+            recording.snapshot = Some(Snapshot {
+                frame: readback.frame as u64,
+                seed: params.seed,
+                size: readback.size,
+                materials: registry.fingerprint(),
+                cells: readback.cells.clone(),
+            });
+        }
+    }
+
+    let frame = params.frame.load(Ordering::SeqCst) as u64;
+    let mut recorded = recorded.lock().unwrap();
+    for &input in actions.read() {
+        recorded.events.push(ReplayEvent { frame, input });
+    }
+}
+
+fn stop_replay_recording(
+    mut stops: EventReader<StopReplay>,
+    mut replayer: ResMut<Replayer>,
+    mut inputs: ResMut<ReplayInputs>,
+) {
+    if stops.read().count() == 0 {
+        return;
+    }
+    let Some(recording) = replayer.recording.take() else {
+        return;
+    };
+    let Some(recorded) = inputs.recording.take() else {
+        return;
+    };
+    let Some(snapshot) = recording.snapshot else {
+        warn!("Stopped the replay before its first step was read back");
+        return;
+    };
+    let events = std::mem::take(&mut recorded.lock().unwrap().events);
+    let replay = Replay::new(snapshot, events);
+    match replay.save(&recording.path) {
+        Ok(()) => info!(
+            "Saved {} inputs to {}",
+            replay.events.len(),
+            recording.path.display()
+        ),
+        Err(err) => error!("Could not save {}: {err}", recording.path.display()),
+    }
+}
+
+// Waits for the material table like `snapshot::load_snapshots`
+#[allow(clippy::too_many_arguments)]
+fn play_replays(
+    mut commands: Commands,
+    mut plays: EventReader<PlayReplay>,
+    mut pending: Local<Vec<PathBuf>>,
+    mut replayer: ResMut<Replayer>,
+    mut inputs: ResMut<ReplayInputs>,
+    mut params: ResMut<AutomataParams>,
+    config: Res<GridConfig>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    pending.extend(plays.read().map(|PlayReplay(path)| path.clone()));
+    let Some(registry) = registry else {
+        return;
+    };
+    for path in pending.drain(..) {
+        if replayer.is_recording() {
+            error!("Could not play {} while recording a replay", path.display());
+            continue;
+        }
+        let replay = Replay::load(&path).and_then(|replay| {
+            replay.snapshot.check(config.size, &registry)?;
+            Ok(replay)
+        });
+        let Replay { snapshot, events } = match replay {
+            Ok(replay) => replay,
+            Err(err) => {
+                error!("Could not play {}: {err}", path.display());
+                continue;
+            }
+        };
+        info!("Playing {} from step {}", path.display(), snapshot.frame);
+        let (exact, other): (Vec<_>, Vec<_>) =
+            events.into_iter().partition(|event| event.input.is_exact());
+        inputs.playback = Some(Arc::new(PlaybackInputs {
+            events: exact,
+            next: AtomicUsize::new(0),
+            seed: AtomicU32::new(snapshot.seed.0),
+        }));
+        replayer.playback = Some(Playback {
+            events: other,
+            next: 0,
+        });
+        snapshot::upload(&mut commands, &mut params, snapshot);
+    }
+}
+
+fn play_back_inputs(
+    mut replayer: ResMut<Replayer>,
+    mut inputs: ResMut<ReplayInputs>,
+    mut params: ResMut<AutomataParams>,
+    mut speed: ResMut<SimulationSpeed>,
+) {
+    let (Some(playback), Some(exact)) = (&mut replayer.playback, &inputs.playback) else {
+        return;
+    };
+    let frame = params.frame.load(Ordering::SeqCst) as u64;
+    while let Some(event) = playback
+        .events
+        .get(playback.next)
+        .filter(|event| event.frame <= frame)
+    {
+        match event.input {
+            ReplayInput::Pause(is_paused) => params.is_paused = is_paused,
+            ReplayInput::Step => params.steps_left.store(1, Ordering::SeqCst),
+            ReplayInput::Material(type_id) => params.brush_type_id = type_id,
+            ReplayInput::BrushRadius(radius) => params.brush_radius = radius,
+            ReplayInput::Heatmap(show_heatmap) => params.show_heatmap = show_heatmap,
+            ReplayInput::Speed(steps_per_second) => *speed = SimulationSpeed::new(steps_per_second),
+            ReplayInput::Paint(_) | ReplayInput::Seed(_) => {}
+        }
+        playback.next += 1;
+    }
+    // So that the session carries on with it once the replay ends
+    let seed = SimulationSeed(exact.seed.load(Ordering::SeqCst));
+    if params.seed != seed {
+        params.seed = seed;
+    }
+
+    if playback.next == playback.events.len()
+        && exact.next.load(Ordering::SeqCst) == exact.events.len()
+    {
+        info!("Replay finished at step {frame}");
+        replayer.playback = None;
+        inputs.playback = None;
+        // The render world paints the last stroke for as long as its copy of the params isn't
+        // extracted again, which only happens once they change
+        params.is_drawing = false;
+    }
+}
+
+// In place of the user's, one stroke per frame, see `GameOfLifeNode` for how the step waits for
+// them. Strokes are only painted once the draw pipeline has loaded.
+fn play_back_strokes(
+    inputs: Res<ReplayInputs>,
+    mut params: ResMut<AutomataParams>,
+    draw_pipeline: Option<Res<AutomataDrawPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+) {
+    let Some(playback) = &inputs.playback else {
+        return;
+    };
+    params.is_drawing = false;
+    let can_paint = draw_pipeline.is_some_and(|pipeline| pipeline.is_ready(&pipeline_cache));
+    let frame = params.frame.load(Ordering::SeqCst) as u64;
+    let mut next = playback.next.load(Ordering::SeqCst);
+    while let Some(event) = playback
+        .events
+        .get(next)
+        .filter(|event| event.frame <= frame)
+    {
+        match event.input {
+            ReplayInput::Seed(seed) => playback.seed.store(seed.0, Ordering::SeqCst),
+            ReplayInput::Paint(_) if !can_paint => break,
+            ReplayInput::Paint(stroke) => {
+                params.is_drawing = true;
+                params.prev_mouse_pos = Vec2::from_array(stroke.start);
+                params.mouse_pos = Vec2::from_array(stroke.end);
+                params.brush_radius = stroke.radius;
+                params.brush_type_id = stroke.type_id;
+                next += 1;
+                break;
+            }
+            _ => {}
+        }
+        next += 1;
+    }
+    playback.next.store(next, Ordering::SeqCst);
+    params.seed = SimulationSeed(playback.seed.load(Ordering::SeqCst));
+}
+
+// Tagged with the step that they are applied before, which the main world only knows roughly
+fn record_strokes(
+    inputs: Res<ReplayInputs>,
+    params: Res<AutomataParams>,
+    draw_pipeline: Option<Res<AutomataDrawPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+) {
+    let Some(recording) = &inputs.recording else {
+        return;
+    };
+    let frame = params.frame.load(Ordering::SeqCst) as u64;
+    let mut recording = recording.lock().unwrap();
+    if recording.seed != Some(params.seed) {
+        recording.seed = Some(params.seed);
+        recording.events.push(ReplayEvent {
+            frame,
+            input: ReplayInput::Seed(params.seed),
+        });
+    }
+    let is_painting = params.is_drawing
+        && draw_pipeline.is_some_and(|pipeline| pipeline.is_ready(&pipeline_cache));
+    if is_painting {
+        recording.events.push(ReplayEvent {
+            frame,
+            input: ReplayInput::Paint(DrawUniform::from(params.as_ref())),
+        });
+    }
+}
@@ -0,0 +1,180 @@
+//! Starting over with a fresh world without restarting.
+//!
+//! [`ResetWorld`] (N for a new seed, Shift+N for the same one again) sends `GameOfLifeNode` back
+//! to its `Init` state, which fills the grid anew from step 0 with the [`Initializer`] chosen
+//! last, and drops the steps that were asked for. An image is imported instead, like a level
+//! given on the command line, see [`crate::import`], and one that can't be leaves the world as it
+//! was. The rest of the world, off the grid, is forgotten, see [`crate::chunk`].
+
+use std::{path::PathBuf, sync::atomic::Ordering};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        renderer::RenderQueue,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    chunk::ChunkStreaming,
+    import::{self, ImportImage, Palette, PaletteHandle},
+    input::AutomataParams,
+    material::MaterialRegistry,
+    pipeline::automata::GameOfLifeBuffers,
+    simulation::InitPattern,
+    snapshot::{self, LoadedSnapshot},
+    GridConfig, SimulationSeed,
+};
+
+/// What the grid is filled with when the world is reset.
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub enum Initializer {
+    /// A wall along the sides and across the middle, and scattered sand, as at startup.
+    #[default]
+    Sand,
+    /// Only the walls.
+    Walls,
+    /// Nothing at all.
+    Empty,
+    /// The level drawn in the image at this path.
+    Image(PathBuf),
+}
+
+impl Initializer {
+    /// What the `init` entry point places, `None` for an image.
+    pub fn pattern(&self) -> Option<InitPattern> {
+        match self {
+            Self::Sand => Some(InitPattern::Sand),
+            Self::Walls => Some(InitPattern::Walls),
+            Self::Empty => Some(InitPattern::Empty),
+            Self::Image(_) => None,
+        }
+    }
+}
+
+/// Fills the grid anew and starts over from step 0.
+#[derive(Event, Clone, Debug, Default)]
+pub struct ResetWorld {
+    /// Seed to start over with, the current one if `None`.
+    pub seed: Option<SimulationSeed>,
+    /// What to fill the grid with, the [`Initializer`] used last if `None`.
+    pub initializer: Option<Initializer>,
+}
+
+/// Resets made so far, and the pattern that `init` places after the last one, for
+/// `GameOfLifeNode` to go back to its `Init` state when the count changes.
+#[derive(Resource, Clone, Copy, Default, ExtractResource)]
+pub(crate) struct WorldResets {
+    pub(crate) count: usize,
+    pub(crate) pattern: InitPattern,
+}
+
+pub struct ResetPlugin;
+impl Plugin for ResetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Initializer>()
+            .init_resource::<WorldResets>()
+            .add_event::<ResetWorld>()
+            .add_plugins(ExtractResourcePlugin::<WorldResets>::default())
+            .add_systems(Update, reset_world);
+
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            prepare_world_reset.in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+// Resets wait for shifts that are under way, whose cells would otherwise be stored into the
+// world after it was forgotten. An image is read in here rather than sent to `import_images`, so
+// that its snapshot comes with the reset, and waits for the palette and the material table.
+#[allow(clippy::too_many_arguments)]
+fn reset_world(
+    mut commands: Commands,
+    mut resets: EventReader<ResetWorld>,
+    mut pending: Local<Option<ResetWorld>>,
+    mut params: ResMut<AutomataParams>,
+    mut initializer: ResMut<Initializer>,
+    mut world_resets: ResMut<WorldResets>,
+    mut streaming: ResMut<ChunkStreaming>,
+    handle: Res<PaletteHandle>,
+    palettes: Res<Assets<Palette>>,
+    config: Res<GridConfig>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    if let Some(reset) = resets.read().last() {
+        *pending = Some(reset.clone());
+    }
+    if streaming.is_shifting() {
+        return;
+    }
+    let Some(reset) = pending.take() else {
+        return;
+    };
+
+    let chosen = reset
+        .initializer
+        .clone()
+        .unwrap_or_else(|| initializer.clone());
+    let seed = reset.seed.unwrap_or(params.seed);
+    let snapshot = match &chosen {
+        Initializer::Image(path) => {
+            let (Some(palette), Some(registry)) = (palettes.get(&handle.0), registry) else {
+                *pending = Some(reset);
+                return;
+            };
+            let import = ImportImage {
+                path: path.clone(),
+                fit: default(),
+            };
+            match import::snapshot_from_image(&import, config.size, 0, seed, palette, &registry) {
+                Ok(snapshot) => Some(snapshot),
+                Err(err) => {
+                    error!("Could not reset to {}: {err}", path.display());
+                    return;
+                }
+            }
+        }
+        _ => None,
+    };
+
+    *initializer = chosen;
+    params.seed = seed;
+    // Steps already asked for are dropped in the render world, where they are taken
+    params.frame.store(0, Ordering::SeqCst);
+    streaming.forget();
+    world_resets.count += 1;
+    if let Some(pattern) = initializer.pattern() {
+        world_resets.pattern = pattern;
+    }
+    match snapshot {
+        Some(snapshot) => snapshot::upload(&mut commands, &mut params, snapshot),
+        None => commands.remove_resource::<LoadedSnapshot>(),
+    }
+    info!("Reset to {:?} with seed {}", *initializer, params.seed.0);
+}
+
+// The render world keeps its copy of a snapshot after the main world drops it, which would have
+// `GameOfLifeNode` skip the `init` pass, unless the snapshot is that of an image imported since.
+// The frame is set again in case a step that was under way when the main world reset has counted
+// on from it since.
+fn prepare_world_reset(
+    mut commands: Commands,
+    render_queue: Res<RenderQueue>,
+    buffers: Res<GameOfLifeBuffers>,
+    params: Res<AutomataParams>,
+    resets: Option<Res<WorldResets>>,
+    snapshot: Option<Res<LoadedSnapshot>>,
+) {
+    if !resets.is_some_and(|resets| resets.is_changed() && resets.count > 0) {
+        return;
+    }
+    if snapshot.is_some_and(|snapshot| !snapshot.is_changed()) {
+        commands.remove_resource::<LoadedSnapshot>();
+    }
+    params.frame.store(0, Ordering::SeqCst);
+    params.steps_left.store(0, Ordering::SeqCst);
+    buffers.wake(&render_queue);
+}
@@ -0,0 +1,249 @@
+//! Resizing a running grid, keeping whatever overlaps the old and the new grid where it was
+//! relative to an anchor.
+//!
+//! The buffers and the image are reallocated in the main world, and the overlapping cells are
+//! copied across on the GPU, row by row, before the next step runs on the new buffers.
+
+use std::mem::size_of;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Buffer, CommandEncoderDescriptor},
+        renderer::{RenderDevice, RenderQueue},
+        ExtractSchedule, MainWorld, Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    cell::Cell,
+    material::MaterialRegistry,
+    pipeline::automata::{GameOfLifeBuffers, GameOfLifeImage},
+    simulation::{random_float, spawn_cell},
+    GridConfig,
+};
+
+/// Which part of the grid stays put when it is resized. Growing adds empty cells on the other
+/// sides, shrinking crops them away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResizeAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl ResizeAnchor {
+    /// Where the cell at the top left corner of a `from` grid ends up in a `to` grid, which
+    /// may be outside of it.
+    pub fn offset(self, from: UVec2, to: UVec2) -> IVec2 {
+        // In halves of the difference in size
+        let halves = match self {
+            Self::TopLeft => IVec2::new(0, 0),
+            Self::Top => IVec2::new(1, 0),
+            Self::TopRight => IVec2::new(2, 0),
+            Self::Left => IVec2::new(0, 1),
+            Self::Center => IVec2::new(1, 1),
+            Self::Right => IVec2::new(2, 1),
+            Self::BottomLeft => IVec2::new(0, 2),
+            Self::Bottom => IVec2::new(1, 2),
+            Self::BottomRight => IVec2::new(2, 2),
+        };
+        (to.as_ivec2() - from.as_ivec2()) * halves / 2
+    }
+}
+
+/// Resizes the grid to `size` cells, keeping what is in it around `anchor`.
+#[derive(Event, Clone, Copy)]
+pub struct ResizeGrid {
+    pub size: UVec2,
+    pub anchor: ResizeAnchor,
+}
+
+/// Cells to carry over from the buffers of the grid before a resize into those after it.
+struct CellCopy {
+    from: Vec<Buffer>,
+    to: Vec<Buffer>,
+    /// Offsets into `from` and `to`, and length, of each row of the overlap, in bytes.
+    rows: Vec<(u64, u64, u64)>,
+}
+
+/// Moved from the main into the render world as they are extracted, so that each copy is made
+/// in the frame that first renders with the resized buffers.
+#[derive(Resource, Default)]
+pub(crate) struct CellCopies(Vec<CellCopy>);
+
+impl CellCopies {
+    /// Copies the cells of the `from` buffers of a `from_size` grid into the `to` buffers of a
+    /// `to_size` grid, with the top left corner of the first at `offset` in the second.
+    pub(crate) fn push(
+        &mut self,
+        from: Vec<Buffer>,
+        to: Vec<Buffer>,
+        from_size: UVec2,
+        to_size: UVec2,
+        offset: IVec2,
+    ) {
+        self.0.push(CellCopy {
+            from,
+            to,
+            rows: overlap_rows(from_size, to_size, offset),
+        });
+    }
+}
+
+pub struct ResizePlugin;
+impl Plugin for ResizePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ResizeGrid>()
+            .init_resource::<CellCopies>()
+            .add_systems(Update, (resize_grid, resize_image).chain());
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<CellCopies>()
+            .add_systems(ExtractSchedule, extract_cell_copies)
+            .add_systems(Render, copy_cells.in_set(RenderSet::PrepareResources));
+    }
+}
+
+pub(crate) fn resize_grid(
+    mut resizes: EventReader<ResizeGrid>,
+    mut config: ResMut<GridConfig>,
+    mut buffers: ResMut<GameOfLifeBuffers>,
+    mut copies: ResMut<CellCopies>,
+    device: Res<RenderDevice>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    for resize in resizes.read() {
+        if resize.size.cmpeq(UVec2::ZERO).any() {
+            error!(
+                "Could not resize the grid to {}x{}",
+                resize.size.x, resize.size.y
+            );
+            continue;
+        }
+        if resize.size == config.size {
+            continue;
+        }
+        let resized = GridConfig {
+            size: resize.size,
+            ..*config
+        };
+        if let Err(err) = resized.check_limits(&device.limits()) {
+            warn!("Could not resize the grid: {err}");
+            continue;
+        }
+        let from = config.size;
+        config.size = resize.size;
+
+        let cells = empty_cells(&config, registry.as_deref());
+        let from_buffers = buffers.in_out.clone();
+        buffers.resize(&device, &config, &cells);
+        copies.push(
+            from_buffers,
+            buffers.in_out.clone(),
+            from,
+            resize.size,
+            resize.anchor.offset(from, resize.size),
+        );
+        info!(
+            "Resized the grid from {}x{} to {}x{}",
+            from.x, from.y, resize.size.x, resize.size.y
+        );
+    }
+}
+
+/// Cells that aren't copied over are left empty, as the `init` entry point leaves them.
+pub(crate) fn empty_cells(config: &GridConfig, registry: Option<&MaterialRegistry>) -> Vec<Cell> {
+    match registry {
+        Some(registry) => {
+            let empty = registry.to_gpu()[0];
+            (0..config.num_cells())
+                .map(|index| spawn_cell(0, &empty, random_float(index as u32)))
+                .collect()
+        }
+        None => vec![Cell::default(); config.num_cells()],
+    }
+}
+
+fn overlap_rows(from: UVec2, to: UVec2, offset: IVec2) -> Vec<(u64, u64, u64)> {
+    let start = offset.max(IVec2::ZERO);
+    let end = (from.as_ivec2() + offset).min(to.as_ivec2());
+    if start.cmpge(end).any() {
+        return Vec::new();
+    }
+    let cell_size = size_of::<Cell>() as u64;
+    (start.y..end.y)
+        .map(|y| {
+            let source = (y - offset.y) as u64 * from.x as u64 + (start.x - offset.x) as u64;
+            let destination = y as u64 * to.x as u64 + start.x as u64;
+            (
+                source * cell_size,
+                destination * cell_size,
+                (end.x - start.x) as u64 * cell_size,
+            )
+        })
+        .collect()
+}
+
+// The colors are redrawn every frame, so the image is only reallocated, along with the sprite
+// and the window that show it
+fn resize_image(
+    config: Res<GridConfig>,
+    mut image: ResMut<GameOfLifeImage>,
+    mut images: ResMut<Assets<Image>>,
+    mut sprites: Query<(&mut Sprite, &mut Handle<Image>, &mut Transform)>,
+    mut windows: Query<&mut Window>,
+) {
+    if !config.is_changed() || config.is_added() {
+        return;
+    }
+    let texture = images.add(crate::create_image(config.size));
+    for (mut sprite, mut handle, mut transform) in &mut sprites {
+        if *handle == image.texture {
+            sprite.custom_size = Some(config.size.as_vec2());
+            *handle = texture.clone();
+            transform.scale = Vec3::splat(config.display_factor as f32);
+        }
+    }
+    images.remove(&image.texture);
+    image.texture = texture;
+
+    let display_size = config.display_size();
+    for mut window in &mut windows {
+        window.resolution.set(display_size.x, display_size.y);
+    }
+}
+
+fn extract_cell_copies(mut main_world: ResMut<MainWorld>, mut copies: ResMut<CellCopies>) {
+    let mut main_copies = main_world.resource_mut::<CellCopies>();
+    copies.0.append(&mut main_copies.0);
+}
+
+// Before the graph runs, so that the next step already reads the resized buffers
+pub(crate) fn copy_cells(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut copies: ResMut<CellCopies>,
+) {
+    if copies.0.is_empty() {
+        return;
+    }
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Resize Encoder"),
+    });
+    for copy in copies.0.drain(..) {
+        for (from, to) in copy.from.iter().zip(&copy.to) {
+            for &(source, destination, length) in &copy.rows {
+                encoder.copy_buffer_to_buffer(from, source, to, destination, length);
+            }
+        }
+    }
+    render_queue.submit([encoder.finish()]);
+}
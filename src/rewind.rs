@@ -0,0 +1,437 @@
+//! Going back to earlier steps, to look at how something came about.
+//!
+//! Every [`RewindConfig::interval`] steps, `GameOfLifeNode` copies the cells that the step wrote
+//! into the next buffer of a ring of [`RewindConfig::depth`] buffers on the GPU, without reading
+//! them back. [`StepBack`] (Backspace) and [`RewindTo`] (the scrubber above the speed slider) copy
+//! one of them back into both `GameOfLifeBuffers::in_out` buffers, set `AutomataParams::frame` to
+//! its step and pause. Stepping on from there takes the same steps again as long as the seed is
+//! the same, so the steps after it are only forgotten once they are taken again or painted over,
+//! and until then the scrubber can go forward as well as back.
+//!
+//! Every buffer of the ring is as large as the grid, so the depth times the grid is what the
+//! history costs in GPU memory, and fewer steps are kept on a grid too large for
+//! [`RewindConfig::memory_budget`] to hold them all. It is forgotten whenever the grid is
+//! replaced as a whole: resized, shifted over the world, loaded from a snapshot or reset, see
+//! [`crate::reset`].
+
+use std::sync::{atomic::Ordering, Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        Render, RenderApp, RenderSet,
+    },
+    ui::RelativeCursorPosition,
+};
+
+use crate::{
+    cell::Cell, input::AutomataParams, pipeline::automata::GameOfLifeBuffers, reset::WorldResets,
+    snapshot::LoadedSnapshot, GridConfig,
+};
+
+const SCRUBBER_WIDTH: f32 = 160.;
+const SCRUBBER_HEIGHT: f32 = 18.;
+
+/// How much history to keep. Insert it before adding [`crate::GameOfLifeComputePlugin`] to keep
+/// another amount than the default.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, ExtractResource)]
+pub struct RewindConfig {
+    /// Number of steps kept.
+    pub depth: usize,
+    /// Steps between two that are kept, 1 to keep every one.
+    pub interval: usize,
+    /// Most bytes of GPU memory that the kept steps take up together.
+    pub memory_budget: u64,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self {
+            depth: 32,
+            interval: 1,
+            memory_budget: 256 << 20,
+        }
+    }
+}
+
+impl RewindConfig {
+    /// Number of steps kept of a grid of `grid_bytes`, the depth or as many as fit in the memory
+    /// budget, and at least one.
+    pub fn depth_within_budget(&self, grid_bytes: u64) -> usize {
+        let fitting = self.memory_budget / grid_bytes.max(1);
+        self.depth
+            .min(fitting.try_into().unwrap_or(usize::MAX))
+            .max(1)
+    }
+}
+
+/// The steps whose cells are kept, by buffer of the ring, shared between the main and the render
+/// world like `readback::Readback`.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct RewindHistory(Arc<Mutex<Vec<Option<usize>>>>);
+
+impl RewindHistory {
+    /// The steps that can be rewound to, oldest first.
+    pub fn steps(&self) -> Vec<usize> {
+        let mut steps: Vec<_> = self.0.lock().unwrap().iter().flatten().copied().collect();
+        steps.sort_unstable();
+        steps
+    }
+
+    fn slot(&self, frame: usize) -> Option<usize> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|step| *step == Some(frame))
+    }
+
+    // Anything after it was kept before going back to an earlier step
+    fn keep(&self, slot: usize, frame: usize) {
+        self.forget_after(frame);
+        self.0.lock().unwrap()[slot] = Some(frame);
+    }
+
+    fn forget_after(&self, frame: usize) {
+        for step in self.0.lock().unwrap().iter_mut() {
+            if step.is_some_and(|step| step > frame) {
+                *step = None;
+            }
+        }
+    }
+
+    fn clear(&self, depth: usize) {
+        let mut steps = self.0.lock().unwrap();
+        steps.clear();
+        steps.resize(depth, None);
+    }
+}
+
+/// Goes back to a step that is still kept, see [`RewindHistory::steps`].
+#[derive(Event, Clone, Copy)]
+pub struct RewindTo(pub usize);
+
+/// Goes back to the last step kept before the current one.
+#[derive(Event, Clone, Copy, Default)]
+pub struct StepBack;
+
+/// The step rewound to last, and the buffer of the ring it is in. Its cells are copied back into
+/// `GameOfLifeBuffers::in_out` before the next step.
+#[derive(Resource, Clone, ExtractResource)]
+struct Rewound {
+    frame: usize,
+    slot: usize,
+}
+
+/// The ring of buffers that the steps are kept in.
+#[derive(Resource)]
+pub(crate) struct RewindBuffers {
+    slots: Vec<Buffer>,
+    interval: usize,
+    history: RewindHistory,
+}
+
+impl RewindBuffers {
+    /// Keeps a copy of `cells` as they are after step `frame`, if it is one to keep.
+    pub(crate) fn keep(&self, encoder: &mut CommandEncoder, cells: &Buffer, frame: usize) {
+        if !frame.is_multiple_of(self.interval) {
+            return;
+        }
+        let slot = (frame / self.interval) % self.slots.len();
+        encoder.copy_buffer_to_buffer(cells, 0, &self.slots[slot], 0, cells.size());
+        self.history.keep(slot, frame);
+    }
+}
+
+#[derive(Component)]
+struct RewindScrubber;
+
+#[derive(Component)]
+struct RewindScrubberFill;
+
+#[derive(Component)]
+struct RewindScrubberLabel;
+
+pub struct RewindPlugin;
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut App) {
+        let config = *app
+            .world_mut()
+            .get_resource_or_insert_with(RewindConfig::default);
+        let history = RewindHistory::default();
+        history.clear(config.depth.max(1));
+        app.insert_resource(history)
+            .add_event::<RewindTo>()
+            .add_event::<StepBack>()
+            .add_plugins(ExtractResourcePlugin::<RewindConfig>::default())
+            .add_plugins(ExtractResourcePlugin::<RewindHistory>::default())
+            .add_plugins(ExtractResourcePlugin::<Rewound>::default())
+            .add_systems(Startup, setup_rewind_scrubber)
+            .add_systems(
+                Update,
+                (
+                    forget_history,
+                    forget_painted_over,
+                    drag_rewind_scrubber,
+                    rewind,
+                    show_rewind,
+                )
+                    .chain(),
+            );
+
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            (prepare_rewind_buffers, prepare_rewound)
+                .chain()
+                .in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+// The kept steps belong to the grid as it was
+fn forget_history(
+    history: Res<RewindHistory>,
+    config: Res<RewindConfig>,
+    grid: Res<GridConfig>,
+    buffers: Option<Res<GameOfLifeBuffers>>,
+    snapshot: Option<Res<LoadedSnapshot>>,
+    resets: Res<WorldResets>,
+) {
+    let is_replaced = buffers.is_some_and(|buffers| buffers.is_changed())
+        || snapshot.is_some_and(|snapshot| snapshot.is_changed())
+        || resets.is_changed();
+    if is_replaced {
+        let grid_bytes = (grid.num_cells() * std::mem::size_of::<Cell>()) as u64;
+        history.clear(config.depth_within_budget(grid_bytes));
+    }
+}
+
+// Stepping on from a painted over step wouldn't lead to the steps kept after it
+fn forget_painted_over(history: Res<RewindHistory>, params: Res<AutomataParams>) {
+    if params.is_drawing {
+        history.forget_after(params.frame.load(Ordering::SeqCst));
+    }
+}
+
+fn rewind(
+    mut commands: Commands,
+    mut rewinds: EventReader<RewindTo>,
+    mut step_backs: EventReader<StepBack>,
+    mut params: ResMut<AutomataParams>,
+    history: Res<RewindHistory>,
+) {
+    let frame = params.frame.load(Ordering::SeqCst);
+    let target = match rewinds.read().last() {
+        Some(RewindTo(step)) => Some(*step),
+        None if step_backs.read().count() > 0 => {
+            let earlier = history.steps().into_iter().rev().find(|step| *step < frame);
+            if earlier.is_none() {
+                info!("No step before step {frame} is kept");
+            }
+            earlier
+        }
+        None => None,
+    };
+    step_backs.clear();
+    let Some(target) = target else {
+        return;
+    };
+    let Some(slot) = history.slot(target) else {
+        warn!("Step {target} is no longer kept");
+        return;
+    };
+
+    // Steps already asked for are dropped in the render world, where they are taken
+    params.frame.store(target, Ordering::SeqCst);
+    params.is_paused = true;
+    commands.insert_resource(Rewound {
+        frame: target,
+        slot,
+    });
+    info!("Rewound to step {target}");
+}
+
+// Remade whenever the grid or the config changes, which also forgets the history
+fn prepare_rewind_buffers(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    buffers: Res<GameOfLifeBuffers>,
+    config: Res<RewindConfig>,
+    history: Res<RewindHistory>,
+    rewind_buffers: Option<Res<RewindBuffers>>,
+) {
+    let interval = config.interval.max(1);
+    let size = buffers.in_out[0].size();
+    let depth = config.depth_within_budget(size);
+    let is_current = rewind_buffers.is_some_and(|rewind_buffers| {
+        rewind_buffers.slots.len() == depth
+            && rewind_buffers.slots[0].size() == size
+            && rewind_buffers.interval == interval
+    });
+    if is_current {
+        return;
+    }
+    if depth < config.depth {
+        warn!(
+            "Keeping only {depth} of {} steps to rewind to, to stay within {} MiB",
+            config.depth,
+            config.memory_budget >> 20
+        );
+    }
+    let slots = (0..depth)
+        .map(|slot| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(&format!("Rewind Buffer {slot}")),
+                size,
+                usage: BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        })
+        .collect();
+    history.clear(depth);
+    commands.insert_resource(RewindBuffers {
+        slots,
+        interval,
+        history: history.clone(),
+    });
+}
+
+// Both buffers, like `snapshot::prepare_loaded_snapshot`. The frame is set again in case a step
+// that was under way when the main world rewound has counted on from it since.
+fn prepare_rewound(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    buffers: Res<GameOfLifeBuffers>,
+    params: Res<AutomataParams>,
+    rewind_buffers: Option<Res<RewindBuffers>>,
+    rewound: Option<Res<Rewound>>,
+) {
+    let (Some(rewind_buffers), Some(rewound)) = (
+        rewind_buffers,
+        rewound.filter(|rewound| rewound.is_changed()),
+    ) else {
+        return;
+    };
+    let Some(slot) = rewind_buffers.slots.get(rewound.slot) else {
+        return;
+    };
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Rewind Encoder"),
+    });
+    for buffer in &buffers.in_out {
+        encoder.copy_buffer_to_buffer(slot, 0, buffer, 0, buffer.size());
+    }
+    render_queue.submit([encoder.finish()]);
+    buffers.wake(&render_queue);
+    params.frame.store(rewound.frame, Ordering::SeqCst);
+    params.steps_left.store(0, Ordering::SeqCst);
+}
+
+fn setup_rewind_scrubber(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(8.),
+                    bottom: Val::Px(8. + SCRUBBER_HEIGHT + 4.),
+                    width: Val::Px(SCRUBBER_WIDTH),
+                    height: Val::Px(SCRUBBER_HEIGHT),
+                    ..default()
+                },
+                background_color: Color::srgba(0., 0., 0., 0.6).into(),
+                ..default()
+            },
+            Interaction::default(),
+            RelativeCursorPosition::default(),
+            RewindScrubber,
+        ))
+        .with_children(|scrubber| {
+            scrubber.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.),
+                        height: Val::Percent(100.),
+                        ..default()
+                    },
+                    background_color: Color::srgba(1., 1., 1., 0.3).into(),
+                    ..default()
+                },
+                RewindScrubberFill,
+            ));
+            scrubber.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 14.,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(4.),
+                    ..default()
+                }),
+                RewindScrubberLabel,
+            ));
+        });
+}
+
+// Pressing anywhere on the scrubber and dragging goes back to the kept step closest to the cursor,
+// the oldest one on the left and the newest one on the right
+fn drag_rewind_scrubber(
+    scrubbers: Query<(&Interaction, &RelativeCursorPosition), With<RewindScrubber>>,
+    history: Res<RewindHistory>,
+    params: Res<AutomataParams>,
+    mut rewinds: EventWriter<RewindTo>,
+) {
+    for (interaction, cursor) in &scrubbers {
+        let Some(position) = cursor
+            .normalized
+            .filter(|_| *interaction == Interaction::Pressed)
+        else {
+            continue;
+        };
+        let steps = history.steps();
+        let (Some(&oldest), Some(&newest)) = (steps.first(), steps.last()) else {
+            continue;
+        };
+        let wanted = oldest as f32 + position.x.clamp(0., 1.) * (newest - oldest) as f32;
+        let closest = steps.iter().copied().min_by(|a, b| {
+            (*a as f32 - wanted)
+                .abs()
+                .total_cmp(&(*b as f32 - wanted).abs())
+        });
+        if let Some(step) = closest.filter(|step| *step != params.frame.load(Ordering::SeqCst)) {
+            rewinds.send(RewindTo(step));
+        }
+    }
+}
+
+fn show_rewind(
+    history: Res<RewindHistory>,
+    params: Res<AutomataParams>,
+    mut fills: Query<&mut Style, With<RewindScrubberFill>>,
+    mut labels: Query<&mut Text, With<RewindScrubberLabel>>,
+) {
+    let frame = params.frame.load(Ordering::SeqCst);
+    let steps = history.steps();
+    let fraction = match (steps.first(), steps.last()) {
+        (Some(&oldest), Some(&newest)) if newest > oldest => {
+            (frame.clamp(oldest, newest) - oldest) as f32 / (newest - oldest) as f32
+        }
+        _ => 1.,
+    };
+    for mut style in &mut fills {
+        style.width = Val::Percent(100. * fraction);
+    }
+    for mut text in &mut labels {
+        let label = format!("step {frame} ({} kept)", steps.len());
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+}
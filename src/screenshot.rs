@@ -0,0 +1,88 @@
+//! PNG screenshots of the grid, without the bloom and the perf overlay that the window adds.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use image::{imageops, RgbaImage};
+
+use crate::{
+    readback::{ImageReadback, RequestImage},
+    utils, GridConfig,
+};
+
+/// Saves what the grid looks like after the latest step to a PNG in the working directory, named
+/// after the time and the step.
+#[derive(Event, Clone, Copy, Default)]
+pub struct TakeScreenshot {
+    /// Scale the image up by [`GridConfig::display_factor`], to the size it has in the window.
+    pub upscale: bool,
+}
+
+pub struct ScreenshotPlugin;
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TakeScreenshot>()
+            .add_systems(Update, take_screenshots);
+    }
+}
+
+/// Turns a readback of `GameOfLifeImage` into an opaque image, `scale` times its size.
+pub fn screenshot_image(readback: &ImageReadback, scale: u32) -> RgbaImage {
+    let mut image = RgbaImage::from_raw(readback.size.x, readback.size.y, readback.pixels.clone())
+        .expect("readback should hold a whole image");
+    // Fading cells are blended into the black background, as in the window
+    for pixel in image.pixels_mut() {
+        let alpha = u16::from(pixel[3]);
+        for channel in &mut pixel.0[..3] {
+            *channel = (u16::from(*channel) * alpha / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+    if scale == 1 {
+        return image;
+    }
+    imageops::resize(
+        &image,
+        image.width() * scale,
+        image.height() * scale,
+        imageops::FilterType::Nearest,
+    )
+}
+
+fn take_screenshots(
+    mut screenshots: EventReader<TakeScreenshot>,
+    mut requests: EventWriter<RequestImage>,
+    mut readbacks: EventReader<ImageReadback>,
+    mut pending: Local<Vec<TakeScreenshot>>,
+    config: Res<GridConfig>,
+) {
+    if let Some(readback) = readbacks.read().last() {
+        let timestamp = utils::timestamp();
+        for screenshot in pending.drain(..) {
+            let (scale, suffix) = if screenshot.upscale {
+                let scale = config.display_factor;
+                (scale, format!("-x{scale}"))
+            } else {
+                (1, String::new())
+            };
+            // Numbered rather than overwriting one taken of the same step within the same second
+            let name = format!("litterbox-{timestamp}-step{}{suffix}", readback.frame);
+            let path = (1..)
+                .map(|number| match number {
+                    1 => format!("{name}.png"),
+                    _ => format!("{name}-{number}.png"),
+                })
+                .find(|path| !Path::new(path).exists())
+                .expect("some number should be unused");
+            match screenshot_image(readback, scale).save(&path) {
+                Ok(()) => info!("Saved step {} to {path}", readback.frame),
+                Err(err) => error!("Could not save {path}: {err}"),
+            }
+        }
+    }
+
+    for screenshot in screenshots.read() {
+        pending.push(*screenshot);
+        requests.send(RequestImage);
+    }
+}
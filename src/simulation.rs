@@ -0,0 +1,605 @@
+//! Headless CPU reference implementation of the rules in `assets/shaders/litterbox.wgsl`.
+//!
+//! Every function here follows its WGSL namesake, down to the random numbers it draws, so a
+//! [`Simulation`] produces the same cells as the compute shaders do. That makes it possible to
+//! test and benchmark the rules without a GPU, and to check the shaders against it.
+
+use bevy::math::{IVec2, UVec2};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    cell::Cell,
+    material::{GpuMaterial, MaterialRegistry, MaterialState, MAX_DISPERSION},
+    reaction::ReactionTable,
+    SimulationSeed, WORKGROUP_SIZE,
+};
+
+const NO_MOVE: IVec2 = IVec2::ZERO;
+const GAS_DRIFT_CHANCE: f32 = 0.3;
+
+/// Same as `hash` in `core.wgsl`.
+pub fn hash(value: u32) -> u32 {
+    let mut state = value;
+    state ^= 2747636419;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state = state.wrapping_mul(2654435769);
+    state
+}
+
+/// Same as `randomFloat` in `core.wgsl`.
+pub fn random_float(value: u32) -> f32 {
+    hash(value) as f32 / 4294967295.0
+}
+
+/// Same as `jitter_color` in `core.wgsl`.
+pub fn jitter_color(material: &GpuMaterial, random: f32) -> [f32; 4] {
+    let offset = (random * 2. - 1.) * material.color_jitter;
+    let [r, g, b, a] = material.color;
+    [
+        (r + offset).clamp(0., 1.),
+        (g + offset).clamp(0., 1.),
+        (b + offset).clamp(0., 1.),
+        a,
+    ]
+}
+
+/// Same as `spawn_cell` in `core.wgsl`.
+pub fn spawn_cell(type_id: i32, material: &GpuMaterial, random: f32) -> Cell {
+    Cell {
+        type_id,
+        lifetime: (material.lifetime as f32 * (0.75 + 0.5 * random)) as u32,
+        temperature: material.temperature,
+        color: jitter_color(material, random),
+        ..Cell::default()
+    }
+}
+
+/// What the `init` entry point fills the grid with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InitPattern {
+    /// A wall along the sides and across the middle, and scattered sand.
+    #[default]
+    Sand,
+    /// Only the walls.
+    Walls,
+    /// Nothing at all.
+    Empty,
+}
+
+/// Materials placed by the `init` entry point, laid out to match `InitParams` in
+/// `litterbox.wgsl`, and uploaded into `GameOfLifeBuffers::init`.
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct InitUniform {
+    pub wall: i32,
+    pub sand: i32,
+    /// An [`InitPattern`], in the order of its variants.
+    pub pattern: u32,
+}
+
+impl InitUniform {
+    pub fn with_pattern(self, pattern: InitPattern) -> Self {
+        Self {
+            pattern: pattern as u32,
+            ..self
+        }
+    }
+
+    pub fn pattern(&self) -> InitPattern {
+        match self.pattern {
+            1 => InitPattern::Walls,
+            2 => InitPattern::Empty,
+            _ => InitPattern::Sand,
+        }
+    }
+}
+
+impl From<&MaterialRegistry> for InitUniform {
+    fn from(registry: &MaterialRegistry) -> Self {
+        Self {
+            wall: registry.id("Wall").unwrap_or_default(),
+            sand: registry.id("Sand").unwrap_or_default(),
+            pattern: InitPattern::Sand as u32,
+        }
+    }
+}
+
+/// A grid of cells laid out like `GameOfLifeBuffers::in_out`, row by row from the top.
+#[derive(Clone)]
+pub struct Grid {
+    width: u32,
+    height: u32,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    /// A grid filled with the empty material.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); (width * height) as usize],
+        }
+    }
+
+    /// A grid of `size` cells, such as those read back from the GPU.
+    pub fn from_cells(size: UVec2, cells: Vec<Cell>) -> Self {
+        assert_eq!(cells.len(), size.x as usize * size.y as usize);
+        Self {
+            width: size.x,
+            height: size.y,
+            cells,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn in_bounds(&self, location: IVec2) -> bool {
+        location.cmpge(IVec2::ZERO).all()
+            && location.x < self.width as i32
+            && location.y < self.height as i32
+    }
+
+    fn idx(&self, location: IVec2) -> usize {
+        (location.y * self.width as i32 + location.x) as usize
+    }
+
+    pub fn get(&self, location: IVec2) -> Cell {
+        self.cells[self.idx(location)]
+    }
+
+    pub fn set(&mut self, location: IVec2, cell: Cell) {
+        let index = self.idx(location);
+        self.cells[index] = cell;
+    }
+
+    /// Number of cells of the given material.
+    pub fn count(&self, type_id: i32) -> usize {
+        self.cells
+            .iter()
+            .filter(|cell| cell.type_id == type_id)
+            .count()
+    }
+}
+
+/// A [`Grid`] together with everything the shaders need to advance it: the material and
+/// reaction tables, the step number and the seed.
+pub struct Simulation {
+    grid: Grid,
+    materials: Vec<GpuMaterial>,
+    reactions: ReactionTable,
+    init: InitUniform,
+    frame: u32,
+    seed: SimulationSeed,
+}
+
+impl Simulation {
+    pub fn new(grid: Grid, registry: &MaterialRegistry, reactions: ReactionTable) -> Self {
+        Self {
+            grid,
+            materials: registry.to_gpu(),
+            reactions,
+            init: InitUniform::from(registry),
+            frame: 0,
+            seed: SimulationSeed::default(),
+        }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.grid
+    }
+
+    /// The step number that the next [`Self::step`] passes to the rules, like `frame` in
+    /// `litterbox.wgsl`.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn set_frame(&mut self, frame: u32) {
+        self.frame = frame;
+    }
+
+    /// The seed of the random choices of [`Self::init`] and [`Self::step`], like `seed` in
+    /// `litterbox.wgsl`.
+    pub fn seed(&self) -> SimulationSeed {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: SimulationSeed) {
+        self.seed = seed;
+    }
+
+    /// What [`Self::init`] fills the grid with, like the pattern of `init_params` in
+    /// `litterbox.wgsl`.
+    pub fn set_init_pattern(&mut self, pattern: InitPattern) {
+        self.init = self.init.with_pattern(pattern);
+    }
+
+    /// Fills the grid like the `init` entry point does, see [`InitPattern`].
+    pub fn init(&mut self) {
+        let pattern = self.init.pattern();
+        let (width, height) = (self.grid.width, self.grid.height);
+        let num_workgroups_x = width.div_ceil(WORKGROUP_SIZE);
+        for location in locations(&self.grid) {
+            let (x, y) = (location.x as u32, location.y as u32);
+            let workgroup_id = (x / WORKGROUP_SIZE, y / WORKGROUP_SIZE, 0);
+            let random_number = random_float(
+                (y * num_workgroups_x + x)
+                    .wrapping_add(workgroup_id.0 + workgroup_id.1 + workgroup_id.2)
+                    ^ hash(self.seed.0),
+            );
+            let type_id = if pattern == InitPattern::Empty {
+                0
+            } else if y == (height / 2).wrapping_sub(1) || x == 0 || x == width - 1 {
+                self.init.wall
+            } else if pattern == InitPattern::Sand && random_number > 0.9 {
+                self.init.sand
+            } else {
+                0
+            };
+            let index = self.grid.idx(location);
+            let cell = spawn_cell(
+                type_id,
+                &self.materials[type_id as usize],
+                random_float(index as u32 ^ hash(self.seed.0)),
+            );
+            self.grid.set(location, cell);
+        }
+    }
+
+    /// Advances the grid by one step, like the `diffuse_heat`, `react`, `claim` and `update`
+    /// passes do on the GPU.
+    pub fn step(&mut self) {
+        self.grid = step(
+            &self.grid,
+            &self.materials,
+            &self.reactions,
+            self.frame,
+            self.seed,
+        );
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+fn step(
+    grid: &Grid,
+    materials: &[GpuMaterial],
+    reactions: &ReactionTable,
+    frame: u32,
+    seed: SimulationSeed,
+) -> Grid {
+    let mut rules = Rules {
+        grid,
+        materials,
+        reactions,
+        frame,
+        seed,
+        intents: Vec::new(),
+        temperatures: Vec::new(),
+        products: Vec::new(),
+    };
+    rules.temperatures = locations(grid)
+        .map(|location| rules.diffuse_heat(location))
+        .collect();
+    rules.products = locations(grid)
+        .map(|location| rules.react(location))
+        .collect();
+    rules.intents = locations(grid)
+        .map(|location| rules.intent(location))
+        .collect();
+
+    let mut output = grid.clone();
+    for location in locations(grid) {
+        output.set(location, rules.update(location));
+    }
+    output
+}
+
+fn locations(grid: &Grid) -> impl Iterator<Item = IVec2> {
+    let (width, height) = (grid.width as i32, grid.height as i32);
+    (0..height).flat_map(move |y| (0..width).map(move |x| IVec2::new(x, y)))
+}
+
+fn is_state(material: &GpuMaterial, state: MaterialState) -> bool {
+    material.state == state as u32
+}
+
+struct Rules<'a> {
+    grid: &'a Grid,
+    materials: &'a [GpuMaterial],
+    reactions: &'a ReactionTable,
+    frame: u32,
+    seed: SimulationSeed,
+    intents: Vec<IVec2>,
+    temperatures: Vec<f32>,
+    products: Vec<i32>,
+}
+
+impl Rules<'_> {
+    fn material(&self, cell: Cell) -> &GpuMaterial {
+        &self.materials[cell.type_id as usize]
+    }
+
+    fn is_empty(&self, location: IVec2) -> bool {
+        self.grid.in_bounds(location)
+            && is_state(self.material(self.grid.get(location)), MaterialState::Empty)
+    }
+
+    fn cell_random(&self, location: IVec2, salt: u32) -> f32 {
+        random_float(
+            self.grid.idx(location) as u32 ^ hash(self.frame ^ hash(salt ^ hash(self.seed.0))),
+        )
+    }
+
+    fn random_side(&self, location: IVec2) -> i32 {
+        if self.cell_random(location, 0) < 0.5 {
+            -1
+        } else {
+            1
+        }
+    }
+
+    fn fall_intent(&self, location: IVec2, dir_y: i32) -> IVec2 {
+        if self.is_empty(location + IVec2::new(0, dir_y)) {
+            return IVec2::new(0, dir_y);
+        }
+        let side = self.random_side(location);
+        if self.is_empty(location + IVec2::new(side, dir_y)) {
+            return IVec2::new(side, dir_y);
+        }
+        if self.is_empty(location + IVec2::new(-side, dir_y)) {
+            return IVec2::new(-side, dir_y);
+        }
+        NO_MOVE
+    }
+
+    fn flow_reach(&self, location: IVec2, side: i32, dispersion: i32) -> i32 {
+        (1..=dispersion)
+            .take_while(|distance| self.is_empty(location + IVec2::new(side * distance, 0)))
+            .last()
+            .unwrap_or(0)
+    }
+
+    fn flow_intent(&self, location: IVec2, dispersion: i32) -> IVec2 {
+        let side = self.random_side(location);
+        let reach = self.flow_reach(location, side, dispersion);
+        if reach > 0 {
+            return IVec2::new(side * reach, 0);
+        }
+        IVec2::new(-side * self.flow_reach(location, -side, dispersion), 0)
+    }
+
+    fn intent(&self, location: IVec2) -> IVec2 {
+        let material = self.material(self.grid.get(location));
+        let dispersion = (material.dispersion as i32).min(MAX_DISPERSION as i32);
+        if is_state(material, MaterialState::Powder) {
+            self.fall_intent(location, 1)
+        } else if is_state(material, MaterialState::Liquid) {
+            let fall = self.fall_intent(location, 1);
+            if fall != NO_MOVE {
+                return fall;
+            }
+            self.flow_intent(location, dispersion)
+        } else if is_state(material, MaterialState::Gas) {
+            let drift = self.flow_intent(location, dispersion);
+            if self.cell_random(location, 1) < GAS_DRIFT_CHANCE && drift != NO_MOVE {
+                return drift;
+            }
+            let rise = self.fall_intent(location, -1);
+            if rise != NO_MOVE {
+                return rise;
+            }
+            drift
+        } else {
+            NO_MOVE
+        }
+    }
+
+    fn intent_at(&self, location: IVec2) -> IVec2 {
+        self.intents[self.grid.idx(location)]
+    }
+
+    fn claims(&self, destination: IVec2, offset: IVec2) -> bool {
+        let source = destination + offset;
+        self.grid.in_bounds(source) && self.intent_at(source) == -offset
+    }
+
+    fn claimant(&self, location: IVec2) -> IVec2 {
+        let side = if self.frame.is_multiple_of(2) { 1 } else { -1 };
+        let candidates = [
+            IVec2::new(0, -1),
+            IVec2::new(side, -1),
+            IVec2::new(-side, -1),
+            IVec2::new(0, 1),
+            IVec2::new(side, 1),
+            IVec2::new(-side, 1),
+        ];
+        let horizontal = (1..=MAX_DISPERSION as i32).flat_map(|distance| {
+            [
+                IVec2::new(side * distance, 0),
+                IVec2::new(-side * distance, 0),
+            ]
+        });
+        candidates
+            .into_iter()
+            .chain(horizontal)
+            .find(|&offset| self.claims(location, offset))
+            .unwrap_or(NO_MOVE)
+    }
+
+    fn is_fluid(material: &GpuMaterial) -> bool {
+        is_state(material, MaterialState::Liquid) || is_state(material, MaterialState::Gas)
+    }
+
+    fn sinks(&self, location: IVec2) -> bool {
+        let below = location + IVec2::new(0, 1);
+        if !self.grid.in_bounds(location)
+            || !self.grid.in_bounds(below)
+            || !(location.y as u32)
+                .wrapping_add(self.frame)
+                .is_multiple_of(2)
+        {
+            return false;
+        }
+        let material = self.material(self.grid.get(location));
+        let below_material = self.material(self.grid.get(below));
+        (is_state(material, MaterialState::Powder) || Self::is_fluid(material))
+            && Self::is_fluid(below_material)
+            && material.density > below_material.density
+            && self.intent_at(location) == NO_MOVE
+            && self.intent_at(below) == NO_MOVE
+    }
+
+    fn age(&self, cell: Cell, random: f32) -> Cell {
+        let material = self.material(cell);
+        if material.lifetime == 0 {
+            return cell;
+        }
+        if cell.lifetime <= 1 {
+            let decays_into = material.decays_into;
+            return Cell {
+                temperature: cell.temperature,
+                ..spawn_cell(decays_into, &self.materials[decays_into as usize], random)
+            };
+        }
+        Cell {
+            lifetime: cell.lifetime - 1,
+            ..cell
+        }
+    }
+
+    fn reaction_partner(&self, location: IVec2) -> IVec2 {
+        let is_vertical = self.frame % 2 == 1;
+        let coordinate = if is_vertical { location.y } else { location.x };
+        let offset = if (coordinate as u32)
+            .wrapping_add(self.frame / 2)
+            .is_multiple_of(2)
+        {
+            1
+        } else {
+            -1
+        };
+        if is_vertical {
+            location + IVec2::new(0, offset)
+        } else {
+            location + IVec2::new(offset, 0)
+        }
+    }
+
+    fn react(&self, location: IVec2) -> i32 {
+        let partner = self.reaction_partner(location);
+        if !self.grid.in_bounds(partner) {
+            return -1;
+        }
+        let first = if self.grid.idx(location) < self.grid.idx(partner) {
+            location
+        } else {
+            partner
+        };
+        let reaction = self.reactions.get(
+            self.grid.get(location).type_id,
+            self.grid.get(partner).type_id,
+        );
+        if self.cell_random(first, 3) < reaction.chance {
+            reaction.into
+        } else {
+            -1
+        }
+    }
+
+    fn heat_flow(&self, location: IVec2, neighbor: IVec2) -> f32 {
+        if !self.grid.in_bounds(neighbor) {
+            return 0.;
+        }
+        let cell = self.grid.get(location);
+        let other = self.grid.get(neighbor);
+        let conductivity = self
+            .material(cell)
+            .conductivity
+            .min(self.material(other).conductivity);
+        conductivity * (other.temperature - cell.temperature)
+    }
+
+    fn change_phase(&self, cell: Cell, random: f32) -> Cell {
+        let material = self.material(cell);
+        let type_id = if material.heats_into >= 0 && cell.temperature > material.heats_above {
+            material.heats_into
+        } else if material.cools_into >= 0 && cell.temperature < material.cools_below {
+            material.cools_into
+        } else {
+            return cell;
+        };
+        Cell {
+            temperature: cell.temperature,
+            ..spawn_cell(type_id, &self.materials[type_id as usize], random)
+        }
+    }
+
+    fn diffuse_heat(&self, location: IVec2) -> f32 {
+        // Summed in the same order as on the GPU, so rounding matches
+        self.grid.get(location).temperature
+            + self.heat_flow(location, location + IVec2::new(0, -1))
+            + self.heat_flow(location, location + IVec2::new(1, 0))
+            + self.heat_flow(location, location + IVec2::new(0, 1))
+            + self.heat_flow(location, location + IVec2::new(-1, 0))
+    }
+
+    fn source_of(&self, location: IVec2) -> IVec2 {
+        if is_state(self.material(self.grid.get(location)), MaterialState::Empty) {
+            return location + self.claimant(location);
+        }
+        let heading = self.intent_at(location);
+        if heading != NO_MOVE {
+            // Only move if we won the target, leaving the empty cell behind
+            if self.claimant(location + heading) == -heading {
+                return location + heading;
+            }
+            return location;
+        }
+        if self.sinks(location) {
+            return location + IVec2::new(0, 1);
+        }
+        if self.sinks(location + IVec2::new(0, -1)) {
+            return location + IVec2::new(0, -1);
+        }
+        location
+    }
+
+    fn moved_cell(&self, location: IVec2) -> Cell {
+        let source = self.source_of(location);
+        let product = self.products[self.grid.idx(source)];
+        if product >= 0 {
+            return spawn_cell(
+                product,
+                &self.materials[product as usize],
+                self.cell_random(source, 4),
+            );
+        }
+        Cell {
+            temperature: self.temperatures[self.grid.idx(source)],
+            ..self.grid.get(source)
+        }
+    }
+
+    fn update(&self, location: IVec2) -> Cell {
+        let result = self.age(self.moved_cell(location), self.cell_random(location, 5));
+        self.change_phase(result, self.cell_random(location, 2))
+    }
+}
@@ -0,0 +1,267 @@
+//! Saving and loading the whole grid to `.litter` files.
+//!
+//! A snapshot starts with an uncompressed header:
+//!
+//! | bytes | content                                                   |
+//! |-------|-----------------------------------------------------------|
+//! | 6     | `LITTER`                                                  |
+//! | 4     | format version, see [`VERSION`]                           |
+//! | 4 + 4 | grid width and height                                     |
+//! | 8     | [`MaterialRegistry::fingerprint`] of the materials in use |
+//! | 8     | step number                                               |
+//! | 4     | [`SimulationSeed`]                                        |
+//!
+//! followed by the zlib compressed [`Cell`]s, row by row from the top, exactly as they are laid
+//! out in `GameOfLifeBuffers::in_out`. All numbers are little endian.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        renderer::RenderQueue,
+        Render, RenderApp, RenderSet,
+    },
+};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use thiserror::Error;
+
+use crate::{
+    cell::Cell,
+    input::AutomataParams,
+    material::MaterialRegistry,
+    pipeline::automata::GameOfLifeBuffers,
+    readback::{CellsReadback, RequestCells},
+    GridConfig, SimulationSeed,
+};
+
+const MAGIC: &[u8; 6] = b"LITTER";
+
+/// Version of the format written by [`Snapshot::write_to`]. Bump it whenever the header or the
+/// layout of [`Cell`] changes.
+pub const VERSION: u32 = 2;
+
+/// Where the quick save and quick load keys save to and load from.
+pub const QUICKSAVE_PATH: &str = "quicksave.litter";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("not a .litter file")]
+    NotASnapshot,
+    #[error("saved with format version {0}, only version {VERSION} is supported")]
+    UnsupportedVersion(u32),
+    #[error("the cells do not fill a {0}x{1} grid")]
+    Truncated(u32, u32),
+    #[error("the grid is {0}x{1}, but the snapshot is {2}x{3}")]
+    SizeMismatch(u32, u32, u32, u32),
+    #[error("saved with a different material table")]
+    MaterialsChanged,
+}
+
+/// Every cell of the grid at a given step.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub frame: u64,
+    /// Seed of the random choices of the steps after this one.
+    pub seed: SimulationSeed,
+    pub size: UVec2,
+    /// [`MaterialRegistry::fingerprint`] of the materials that the cells' `type_id`s refer to.
+    pub materials: u64,
+    pub cells: Vec<Cell>,
+}
+
+impl Snapshot {
+    pub fn write_to(&self, mut writer: impl Write) -> Result<(), SnapshotError> {
+        writer.write_all(MAGIC)?;
+        for value in [VERSION, self.size.x, self.size.y] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for value in [self.materials, self.frame] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&self.seed.0.to_le_bytes())?;
+        let mut encoder = ZlibEncoder::new(writer, Compression::default());
+        encoder.write_all(bytemuck::cast_slice(&self.cells))?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self, SnapshotError> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let width = u32::from_le_bytes(read_array(&mut reader)?);
+        let height = u32::from_le_bytes(read_array(&mut reader)?);
+        let materials = u64::from_le_bytes(read_array(&mut reader)?);
+        let frame = u64::from_le_bytes(read_array(&mut reader)?);
+        let seed = SimulationSeed(u32::from_le_bytes(read_array(&mut reader)?));
+
+        let mut bytes = Vec::new();
+        ZlibDecoder::new(reader).read_to_end(&mut bytes)?;
+        if bytes.len() != width as usize * height as usize * size_of::<Cell>() {
+            return Err(SnapshotError::Truncated(width, height));
+        }
+        Ok(Self {
+            frame,
+            seed,
+            size: UVec2::new(width, height),
+            materials,
+            cells: bytemuck::pod_collect_to_vec(&bytes),
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Checks that the snapshot can be loaded into a `size` grid of `registry`'s materials.
+    pub fn check(&self, size: UVec2, registry: &MaterialRegistry) -> Result<(), SnapshotError> {
+        if self.size != size {
+            return Err(SnapshotError::SizeMismatch(
+                size.x,
+                size.y,
+                self.size.x,
+                self.size.y,
+            ));
+        }
+        if self.materials != registry.fingerprint() {
+            return Err(SnapshotError::MaterialsChanged);
+        }
+        Ok(())
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Saves the cells as they are after the latest step, once they have been read back.
+#[derive(Event)]
+pub struct SaveSnapshot(pub PathBuf);
+
+/// Replaces every cell with those of a snapshot, and carries on from its step.
+#[derive(Event)]
+pub struct LoadSnapshot(pub PathBuf);
+
+/// The snapshot loaded or the image imported last, if any. Its cells are uploaded to the GPU in
+/// place of running the `init` entry point.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct LoadedSnapshot(Arc<Snapshot>);
+
+pub struct SnapshotPlugin;
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveSnapshot>()
+            .add_event::<LoadSnapshot>()
+            .add_plugins(ExtractResourcePlugin::<LoadedSnapshot>::default())
+            .add_systems(Update, (save_snapshots, load_snapshots));
+
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            prepare_loaded_snapshot.in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+// Saves wait for the next readback, and both wait for the material table, so that snapshots can
+// be saved and loaded from the first frame on
+fn save_snapshots(
+    mut saves: EventReader<SaveSnapshot>,
+    mut requests: EventWriter<RequestCells>,
+    mut readbacks: EventReader<CellsReadback>,
+    mut pending: Local<Vec<PathBuf>>,
+    params: Res<AutomataParams>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    if let (Some(readback), Some(registry)) = (readbacks.read().last(), registry) {
+        let snapshot = Snapshot {
+            frame: readback.frame as u64,
+            seed: params.seed,
+            size: readback.size,
+            materials: registry.fingerprint(),
+            cells: readback.cells.clone(),
+        };
+        for path in pending.drain(..) {
+            match snapshot.save(&path) {
+                Ok(()) => info!("Saved {}", path.display()),
+                Err(err) => error!("Could not save {}: {err}", path.display()),
+            }
+        }
+    }
+
+    for SaveSnapshot(path) in saves.read() {
+        pending.push(path.clone());
+        requests.send(RequestCells);
+    }
+}
+
+fn load_snapshots(
+    mut commands: Commands,
+    mut loads: EventReader<LoadSnapshot>,
+    mut pending: Local<Vec<PathBuf>>,
+    mut params: ResMut<AutomataParams>,
+    config: Res<GridConfig>,
+    registry: Option<Res<MaterialRegistry>>,
+) {
+    pending.extend(loads.read().map(|LoadSnapshot(path)| path.clone()));
+    let Some(registry) = registry else {
+        return;
+    };
+    for path in pending.drain(..) {
+        let snapshot = Snapshot::load(&path)
+            .and_then(|snapshot| snapshot.check(config.size, &registry).map(|()| snapshot));
+        match snapshot {
+            Ok(snapshot) => {
+                info!("Loaded {}", path.display());
+                upload(&mut commands, &mut params, snapshot);
+            }
+            Err(err) => error!("Could not load {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Replaces every cell with those of `snapshot` before the next step, which is `snapshot.frame`,
+/// and carries on with its seed.
+pub(crate) fn upload(commands: &mut Commands, params: &mut AutomataParams, snapshot: Snapshot) {
+    params
+        .frame
+        .store(snapshot.frame as usize, Ordering::SeqCst);
+    params.seed = snapshot.seed;
+    commands.insert_resource(LoadedSnapshot(Arc::new(snapshot)));
+}
+
+// Both buffers, so that it doesn't matter which one the next step reads from
+fn prepare_loaded_snapshot(
+    render_queue: Res<RenderQueue>,
+    buffers: Res<GameOfLifeBuffers>,
+    snapshot: Option<Res<LoadedSnapshot>>,
+) {
+    let Some(snapshot) = snapshot.filter(|snapshot| snapshot.is_changed()) else {
+        return;
+    };
+    for buffer in &buffers.in_out {
+        render_queue.write_buffer(buffer, 0, bytemuck::cast_slice(&snapshot.0.cells));
+    }
+    buffers.wake(&render_queue);
+}
@@ -0,0 +1,225 @@
+//! How fast the simulation runs, from slow motion to several steps per frame.
+//!
+//! Steps fall due at [`SimulationSpeed::steps_per_second`] and are added to
+//! `AutomataParams::steps_left`, which `GameOfLifeNode` takes up to [`MAX_STEPS_PER_FRAME`] of
+//! each frame. Steps that fall due while that many are still waiting are dropped, so that a
+//! speed the GPU can't keep up with runs as fast as it can rather than falling further and
+//! further behind.
+//!
+//! Comma and period halve and double the speed, and the slider in the bottom left corner sets it
+//! on a logarithmic scale. Only the last step of a frame is shown, so while recording, see
+//! [`crate::recording`], a single step is taken per frame, and speeds above the frame rate run
+//! no faster than it.
+
+use std::{sync::atomic::Ordering, time::Duration};
+
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{input::AutomataParams, replay::ReplayInput};
+
+/// Slowest speed that can be set.
+pub const MIN_STEPS_PER_SECOND: f32 = 0.25;
+
+/// Fastest speed that can be set, a power of two times [`MIN_STEPS_PER_SECOND`].
+pub const MAX_STEPS_PER_SECOND: f32 = 256.;
+
+/// Most steps that `GameOfLifeNode` takes in a single frame.
+pub const MAX_STEPS_PER_FRAME: usize = 8;
+
+const SLIDER_WIDTH: f32 = 160.;
+const SLIDER_HEIGHT: f32 = 18.;
+
+/// Steps taken per second while the simulation isn't paused. Insert it before adding
+/// [`crate::GameOfLifeComputePlugin`] to start at another than the default.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct SimulationSpeed {
+    pub steps_per_second: f32,
+}
+
+impl Default for SimulationSpeed {
+    fn default() -> Self {
+        Self {
+            steps_per_second: 2.,
+        }
+    }
+}
+
+impl SimulationSpeed {
+    /// Clamped to the speeds that can be set, NaN giving the default.
+    pub fn new(steps_per_second: f32) -> Self {
+        if steps_per_second.is_nan() {
+            return Self::default();
+        }
+        Self {
+            steps_per_second: steps_per_second.clamp(MIN_STEPS_PER_SECOND, MAX_STEPS_PER_SECOND),
+        }
+    }
+
+    pub fn faster(self) -> Self {
+        Self::new(self.steps_per_second * 2.)
+    }
+
+    pub fn slower(self) -> Self {
+        Self::new(self.steps_per_second / 2.)
+    }
+
+    /// Where the speed is between the slowest (0) and the fastest (1), on a logarithmic scale.
+    pub fn fraction(self) -> f32 {
+        (self.steps_per_second / MIN_STEPS_PER_SECOND).log2()
+            / (MAX_STEPS_PER_SECOND / MIN_STEPS_PER_SECOND).log2()
+    }
+
+    pub fn from_fraction(fraction: f32) -> Self {
+        let range = MAX_STEPS_PER_SECOND / MIN_STEPS_PER_SECOND;
+        Self::new(MIN_STEPS_PER_SECOND * range.powf(fraction.clamp(0., 1.)))
+    }
+
+    fn step_duration(self) -> Duration {
+        Duration::from_secs_f32(1. / self.steps_per_second)
+    }
+}
+
+#[derive(Resource)]
+struct StepTimer(Timer);
+
+#[derive(Component)]
+struct SpeedSlider;
+
+#[derive(Component)]
+struct SpeedSliderFill;
+
+#[derive(Component)]
+struct SpeedSliderLabel;
+
+pub struct SpeedPlugin;
+impl Plugin for SpeedPlugin {
+    fn build(&self, app: &mut App) {
+        let speed = *app
+            .world_mut()
+            .get_resource_or_insert_with(SimulationSpeed::default);
+        app.insert_resource(StepTimer(Timer::new(
+            speed.step_duration(),
+            TimerMode::Repeating,
+        )))
+        .add_systems(Startup, setup_speed_slider)
+        .add_systems(Update, (drag_speed_slider, show_speed).chain())
+        .add_systems(FixedUpdate, queue_steps);
+    }
+}
+
+fn queue_steps(
+    mut timer: ResMut<StepTimer>,
+    params: Res<AutomataParams>,
+    speed: Res<SimulationSpeed>,
+    time: Res<Time>,
+) {
+    if speed.is_changed() {
+        timer.0.set_duration(speed.step_duration());
+    }
+    if params.is_paused {
+        return;
+    }
+    timer.0.tick(time.delta());
+    let due = timer.0.times_finished_this_tick() as usize;
+    if due == 0 {
+        return;
+    }
+    // Never drops steps that were asked for otherwise, like with F
+    let _ = params
+        .steps_left
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |steps_left| {
+            Some((steps_left + due).min(MAX_STEPS_PER_FRAME).max(steps_left))
+        });
+}
+
+fn setup_speed_slider(mut commands: Commands, speed: Res<SimulationSpeed>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(8.),
+                    bottom: Val::Px(8.),
+                    width: Val::Px(SLIDER_WIDTH),
+                    height: Val::Px(SLIDER_HEIGHT),
+                    ..default()
+                },
+                background_color: Color::srgba(0., 0., 0., 0.6).into(),
+                ..default()
+            },
+            Interaction::default(),
+            RelativeCursorPosition::default(),
+            SpeedSlider,
+        ))
+        .with_children(|slider| {
+            slider.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100. * speed.fraction()),
+                        height: Val::Percent(100.),
+                        ..default()
+                    },
+                    background_color: Color::srgba(1., 1., 1., 0.3).into(),
+                    ..default()
+                },
+                SpeedSliderFill,
+            ));
+            slider.spawn((
+                TextBundle::from_section(
+                    speed_label(*speed),
+                    TextStyle {
+                        font_size: 14.,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(4.),
+                    ..default()
+                }),
+                SpeedSliderLabel,
+            ));
+        });
+}
+
+// Pressing anywhere on the slider and dragging sets the speed from where the cursor is
+fn drag_speed_slider(
+    sliders: Query<(&Interaction, &RelativeCursorPosition), With<SpeedSlider>>,
+    mut speed: ResMut<SimulationSpeed>,
+    mut inputs: EventWriter<ReplayInput>,
+) {
+    for (interaction, cursor) in &sliders {
+        let Some(position) = cursor
+            .normalized
+            .filter(|_| *interaction == Interaction::Pressed)
+        else {
+            continue;
+        };
+        let dragged = SimulationSpeed::from_fraction(position.x);
+        if dragged != *speed {
+            *speed = dragged;
+            inputs.send(ReplayInput::Speed(speed.steps_per_second));
+        }
+    }
+}
+
+fn show_speed(
+    speed: Res<SimulationSpeed>,
+    mut fills: Query<&mut Style, With<SpeedSliderFill>>,
+    mut labels: Query<&mut Text, With<SpeedSliderLabel>>,
+) {
+    if !speed.is_changed() {
+        return;
+    }
+    for mut style in &mut fills {
+        style.width = Val::Percent(100. * speed.fraction());
+    }
+    for mut text in &mut labels {
+        text.sections[0].value = speed_label(*speed);
+    }
+}
+
+// To two decimals, which is as fine as the slider can be set
+fn speed_label(speed: SimulationSpeed) -> String {
+    format!("{} steps/s", (speed.steps_per_second * 100.).round() / 100.)
+}
@@ -0,0 +1,161 @@
+//! Tracking which tiles of the grid have changed, so that the step and the color pass only work
+//! where something is going on.
+//!
+//! A tile is the square of cells that a workgroup covers. Whatever changes a cell marks its tile
+//! twice, see `chunks.wgsl`: once to keep it and the tiles around it awake for the next steps,
+//! and once to have it colored again. Both the step and the color pass are dispatched indirectly
+//! over the chunks that have marked tiles in them, see [`crate::chunk`], and skip the workgroups
+//! of the other tiles in those chunks.
+//!
+//! How many tiles were stepped and colored is read back into [`TileStats`] a frame or two later,
+//! and shown in the perf UI by [`PerfUiEntryActiveTiles`].
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, OnceLock,
+};
+
+use bevy::{
+    ecs::system::{lifetimeless::SRes, SystemParam},
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        Render, RenderApp, RenderSet,
+    },
+};
+use iyes_perf_ui::{entry::PerfUiEntry, utils::next_sort_key};
+
+use crate::{
+    pipeline::automata::GameOfLifeBuffers,
+    readback::{create_staging_buffer, map_staging_buffer},
+    GridConfig,
+};
+
+/// How many tiles the GPU has lately stepped and colored, shared between the main and the render
+/// world like `readback::Readback`.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct TileStats(Arc<TileCounts>);
+
+#[derive(Default)]
+struct TileCounts {
+    awake: AtomicU32,
+    recolored: AtomicU32,
+}
+
+impl TileStats {
+    /// Tiles that the last step went over.
+    pub fn awake(&self) -> u32 {
+        self.0.awake.load(Ordering::Relaxed)
+    }
+
+    /// Tiles colored in the last frame.
+    pub fn recolored(&self) -> u32 {
+        self.0.recolored.load(Ordering::Relaxed)
+    }
+}
+
+pub struct TilePlugin;
+impl Plugin for TilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileStats>()
+            .add_plugins(ExtractResourcePlugin::<TileStats>::default());
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<PendingTileStats>()
+            .add_systems(Render, read_back_tile_stats.in_set(RenderSet::Cleanup));
+    }
+}
+
+struct PendingTileStat {
+    staging: Buffer,
+    is_mapped: Arc<OnceLock<Result<(), BufferAsyncError>>>,
+}
+
+#[derive(Resource, Default)]
+struct PendingTileStats(Option<PendingTileStat>);
+
+// One read back at a time, copied after the frame's passes have been submitted
+fn read_back_tile_stats(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    buffers: Option<Res<GameOfLifeBuffers>>,
+    stats: Res<TileStats>,
+    mut pending: ResMut<PendingTileStats>,
+) {
+    if let Some(stat) = &pending.0 {
+        match stat.is_mapped.get() {
+            None => return,
+            Some(Ok(())) => {
+                let [awake, recolored]: [u32; 2] =
+                    bytemuck::pod_read_unaligned(&stat.staging.slice(..).get_mapped_range());
+                stats.0.awake.store(awake, Ordering::Relaxed);
+                stats.0.recolored.store(recolored, Ordering::Relaxed);
+            }
+            Some(Err(err)) => warn!("Could not read back the tile stats: {err}"),
+        }
+        pending.0 = None;
+    }
+
+    let Some(buffers) = buffers else {
+        return;
+    };
+    let staging = create_staging_buffer(&render_device, buffers.tile_stats.size());
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Tile Stats Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(&buffers.tile_stats, 0, &staging, 0, staging.size());
+    render_queue.submit([encoder.finish()]);
+
+    let is_mapped = map_staging_buffer(&render_device, &staging);
+    pending.0 = Some(PendingTileStat { staging, is_mapped });
+}
+
+/// Perf UI entry showing how many of the tiles of the grid the last step went over, registered
+/// with `add_perf_ui_simple_entry`.
+#[derive(Component, Debug, Clone)]
+pub struct PerfUiEntryActiveTiles {
+    /// Custom label. If empty (default), the default label will be used.
+    pub label: String,
+    /// Sort key (control where the entry will appear in the Perf UI).
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryActiveTiles {
+    fn default() -> Self {
+        Self {
+            label: String::new(),
+            sort_key: next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryActiveTiles {
+    /// Awake tiles and all tiles.
+    type Value = (u32, usize);
+    type SystemParam = (SRes<TileStats>, SRes<GridConfig>);
+
+    fn label(&self) -> &str {
+        if self.label.is_empty() {
+            "Active Tiles"
+        } else {
+            &self.label
+        }
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        (stats, config): &mut <Self::SystemParam as SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        Some((stats.awake(), config.num_tiles()))
+    }
+
+    fn format_value(&self, (awake, total): &Self::Value) -> String {
+        format!("{awake}/{total}")
+    }
+}
@@ -1,4 +1,13 @@
-use bevy::render::{render_resource::{Buffer, BufferInitDescriptor, BufferUsages}, renderer::RenderDevice};
+use bevy::{
+    math::Vec2,
+    render::{
+        render_resource::{Buffer, BufferInitDescriptor, BufferUsages},
+        renderer::RenderDevice,
+    },
+    utils::SystemTime,
+};
+
+use crate::GridConfig;
 
 pub fn create_uniform_buffer<T: bytemuck::Pod + bytemuck::Zeroable>(
     device: &RenderDevice,
@@ -20,6 +29,61 @@ pub fn create_storage_buffer_with_data<T: bytemuck::Pod + bytemuck::Zeroable>(
     device.create_buffer_with_data(&BufferInitDescriptor {
         label,
         contents: bytemuck::cast_slice(data),
-        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        // COPY_SRC so that the cells can be read back, see `readback`
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
     })
 }
+
+pub fn create_indirect_buffer<T: bytemuck::Pod + bytemuck::Zeroable>(
+    device: &RenderDevice,
+    data: &[T],
+    label: Option<&str>,
+) -> Buffer {
+    device.create_buffer_with_data(&BufferInitDescriptor {
+        label,
+        contents: bytemuck::cast_slice(data),
+        // Written by a compute shader, then used to dispatch others
+        usage: BufferUsages::STORAGE
+            | BufferUsages::INDIRECT
+            | BufferUsages::COPY_DST
+            | BufferUsages::COPY_SRC,
+    })
+}
+
+/// Converts a world position (with y pointing down) into canvas (cell) coordinates.
+/// The canvas sprite is centered on the origin and scaled up by `GridConfig::display_factor`.
+pub fn world_pos_to_canvas_pos(world_pos: Vec2, config: &GridConfig) -> Vec2 {
+    world_pos / config.display_factor as f32 + config.size.as_vec2() / 2.
+}
+
+/// The current UTC date and time as `2024-07-31_18-05-09`, for naming files.
+pub fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, time) = ((seconds / 86_400) as i64, seconds % 86_400);
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}",
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
@@ -0,0 +1,27 @@
+use std::mem::{align_of, offset_of, size_of};
+
+use litterbox::cell::{wgsl_module, Cell};
+
+#[test]
+fn cells_have_the_wgsl_layout() {
+    assert_eq!(size_of::<Cell>(), 32);
+    assert_eq!(align_of::<Cell>(), 4);
+    assert_eq!(offset_of!(Cell, type_id), 0);
+    assert_eq!(offset_of!(Cell, lifetime), 4);
+    assert_eq!(offset_of!(Cell, temperature), 8);
+    assert_eq!(offset_of!(Cell, color), 16);
+}
+
+#[test]
+fn generated_module_declares_the_fields_in_order() {
+    assert_eq!(
+        wgsl_module(),
+        "#define_import_path litterbox::cell\n\n\
+         struct Cell {\n    \
+         type_id: i32,\n    \
+         lifetime: u32,\n    \
+         temperature: f32,\n    \
+         color: vec4<f32>,\n\
+         }\n"
+    );
+}
@@ -0,0 +1,96 @@
+use bevy::{
+    math::{IVec2, UVec2},
+    utils::default,
+};
+use litterbox::{
+    chunk::CHUNK_SIZE,
+    differential::{compare, GpuSimulation},
+    simulation::{random_float, spawn_cell, Grid, Simulation},
+    GridConfig,
+};
+
+const STEPS: u32 = 25;
+const CHECKS: u32 = 8;
+
+#[test]
+fn sleeping_chunks_match_the_cpu_simulation() {
+    let mut gpu = GpuSimulation::with_config(GridConfig {
+        size: UVec2::new(10 * CHUNK_SIZE, CHUNK_SIZE),
+        ..default()
+    });
+    gpu.step(1);
+    let size = gpu.size();
+
+    // A body of water on a floor, that takes a while to spread to the far chunks
+    let mut grid = Grid::new(size.x, size.y);
+    let materials = gpu.registry().to_gpu();
+    let wall = gpu.registry().id("Wall").unwrap();
+    let water = gpu.registry().id("Water").unwrap();
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            let type_id = if y == size.y as i32 - 1 {
+                wall
+            } else if x < 32 && y < 48 {
+                water
+            } else {
+                continue;
+            };
+            let random = random_float((y * size.x as i32 + x) as u32);
+            let cell = spawn_cell(type_id, &materials[type_id as usize], random);
+            grid.set(IVec2::new(x, y), cell);
+        }
+    }
+    let mut cpu = Simulation::new(grid, gpu.registry(), gpu.reactions().clone());
+    cpu.set_frame(gpu.frame());
+    gpu.set_cells(cpu.grid().cells());
+
+    for check in 0..CHECKS {
+        gpu.step(STEPS);
+        for _ in 0..STEPS {
+            cpu.step();
+        }
+        if let Err(divergence) = compare(cpu.frame(), cpu.grid(), &gpu.cells(), gpu.registry()) {
+            panic!("{divergence}");
+        }
+        if check == 0 {
+            assert!(
+                gpu.awake_chunks() < size.x / CHUNK_SIZE,
+                "the chunks that the water hasn't reached yet should be asleep"
+            );
+        }
+    }
+}
+
+#[test]
+fn shifting_the_grid_away_and_back_keeps_its_cells() {
+    let mut gpu = GpuSimulation::new();
+    gpu.step(10);
+    let size = gpu.size();
+    let before = Grid::from_cells(size, gpu.cells());
+
+    // The right half moves over to the left, the world to the right hasn't been visited yet
+    gpu.shift(IVec2::X);
+    let empty = gpu.registry().to_gpu()[0];
+    let mut shifted = Grid::new(size.x, size.y);
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            let location = IVec2::new(x, y);
+            let source = location + IVec2::new(CHUNK_SIZE as i32, 0);
+            let cell = if before.in_bounds(source) {
+                before.get(source)
+            } else {
+                spawn_cell(0, &empty, random_float((y * size.x as i32 + x) as u32))
+            };
+            shifted.set(location, cell);
+        }
+    }
+    if let Err(divergence) = compare(gpu.frame(), &shifted, &gpu.cells(), gpu.registry()) {
+        panic!("{divergence}");
+    }
+
+    // and the left half comes back from where it was stored
+    gpu.shift(-IVec2::X);
+    if let Err(divergence) = compare(gpu.frame(), &before, &gpu.cells(), gpu.registry()) {
+        panic!("{divergence}");
+    }
+}
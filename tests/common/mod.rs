@@ -0,0 +1,20 @@
+//! Fixtures shared by the tests, built from the assets the game ships with.
+
+#![allow(dead_code)]
+
+use litterbox::{
+    material::{MaterialRegistry, MaterialTable},
+    reaction::{ReactionList, ReactionTable},
+};
+
+pub fn registry() -> MaterialRegistry {
+    let table = MaterialTable::from_bytes(include_bytes!("../../assets/litterbox.materials.ron"))
+        .expect("material table should parse");
+    MaterialRegistry::new(table.materials).expect("material table should be valid")
+}
+
+pub fn reactions(registry: &MaterialRegistry) -> ReactionTable {
+    let list = ReactionList::from_bytes(include_bytes!("../../assets/litterbox.reactions.ron"))
+        .expect("reactions should parse");
+    ReactionTable::new(&list, registry).expect("reactions should be valid")
+}
@@ -0,0 +1,50 @@
+use bevy::{math::UVec2, render::settings::WgpuLimits};
+use litterbox::{config::GridConfigError, GridConfig};
+
+#[test]
+fn configs_keep_the_defaults_they_leave_out() {
+    let config = GridConfig::from_bytes(include_bytes!("../assets/litterbox.config.ron"))
+        .expect("config should parse");
+    assert_eq!(config, GridConfig::default());
+
+    let config = GridConfig::from_bytes(b"(size: (256, 96))").expect("config should parse");
+    assert_eq!(config.size, UVec2::new(256, 96));
+    assert_eq!(config.display_factor, GridConfig::default().display_factor);
+}
+
+#[test]
+fn bad_configs_are_rejected() {
+    assert!(matches!(
+        GridConfig::from_bytes(b"(size: (0, 64))"),
+        Err(GridConfigError::EmptySize(0, 64))
+    ));
+    assert!(matches!(
+        GridConfig::from_bytes(b"(display_factor: 0)"),
+        Err(GridConfigError::ZeroDisplayFactor)
+    ));
+    assert!(matches!(
+        GridConfig::from_bytes(b"(width: 64)"),
+        Err(GridConfigError::Ron(_))
+    ));
+}
+
+#[test]
+fn grids_larger_than_the_gpu_allows_are_shrunk() {
+    let limits = WgpuLimits::default();
+    let config = GridConfig::default();
+    assert!(config.check_limits(&limits).is_ok());
+    assert_eq!(config.fit_limits(&limits), config);
+
+    let config = GridConfig {
+        size: UVec2::new(100_000, 50_000),
+        display_factor: 100_000,
+    };
+    assert!(matches!(
+        config.check_limits(&limits),
+        Err(GridConfigError::TooLarge(100_000, 50_000))
+    ));
+    assert!(config.display_size().is_finite());
+    let fitted = config.fit_limits(&limits);
+    assert!(fitted.check_limits(&limits).is_ok());
+    assert_eq!(fitted.size.x, 2 * fitted.size.y);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use bevy::math::IVec2;
+use common::{reactions, registry};
+use litterbox::simulation::{random_float, Grid, Simulation};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+const STEPS: u32 = 2000;
+
+#[test]
+fn movement_conserves_materials() {
+    let registry = registry();
+    let type_ids: Vec<i32> = ["Air", "Wall", "Sand", "Water"]
+        .into_iter()
+        .map(|name| registry.id(name).unwrap())
+        .collect();
+
+    let mut grid = Grid::new(WIDTH, HEIGHT);
+    for y in 0..HEIGHT as i32 {
+        for x in 0..WIDTH as i32 {
+            let location = IVec2::new(x, y);
+            let random = random_float((y * WIDTH as i32 + x) as u32);
+            let type_id = type_ids[(random * type_ids.len() as f32) as usize % type_ids.len()];
+            let mut cell = grid.get(location);
+            cell.type_id = type_id;
+            grid.set(location, cell);
+        }
+    }
+    let totals: Vec<usize> = type_ids.iter().map(|&id| grid.count(id)).collect();
+
+    let mut simulation = Simulation::new(grid, &registry, reactions(&registry));
+    for frame in 0..STEPS {
+        simulation.step();
+        let grid = simulation.grid();
+        let current: Vec<usize> = type_ids.iter().map(|&id| grid.count(id)).collect();
+        assert_eq!(current, totals, "material totals changed at frame {frame}");
+    }
+}
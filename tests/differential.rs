@@ -0,0 +1,70 @@
+use bevy::{
+    math::{IVec2, UVec2},
+    utils::default,
+};
+use litterbox::{
+    differential::{compare, GpuSimulation},
+    simulation::{hash, random_float, spawn_cell, Grid, Simulation},
+    GridConfig,
+};
+
+const STEPS: u32 = 25;
+const CHECKS: u32 = 4;
+
+fn check(gpu: &mut GpuSimulation, cpu: &mut Simulation) {
+    for _ in 0..CHECKS {
+        gpu.step(STEPS);
+        for _ in 0..STEPS {
+            cpu.step();
+        }
+        let cells = gpu.cells();
+        if let Err(divergence) = compare(cpu.frame(), cpu.grid(), &cells, gpu.registry()) {
+            panic!("{divergence}");
+        }
+    }
+}
+
+#[test]
+fn compute_shaders_match_the_cpu_simulation() {
+    let mut gpu = GpuSimulation::new();
+    check_scenes(&mut gpu);
+}
+
+#[test]
+fn compute_shaders_match_on_grids_of_partial_workgroups() {
+    let mut gpu = GpuSimulation::with_config(GridConfig {
+        size: UVec2::new(100, 37),
+        ..default()
+    });
+    check_scenes(&mut gpu);
+}
+
+fn check_scenes(gpu: &mut GpuSimulation) {
+    let size = gpu.size();
+
+    // Walls and sand from the `init` entry point
+    let mut cpu = Simulation::new(
+        Grid::new(size.x, size.y),
+        gpu.registry(),
+        gpu.reactions().clone(),
+    );
+    cpu.init();
+    check(gpu, &mut cpu);
+
+    // Every material, so that heat, phase changes and reactions all come into play
+    let mut grid = Grid::new(size.x, size.y);
+    let materials = gpu.registry().to_gpu();
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            let location = IVec2::new(x, y);
+            let random = hash((y * size.x as i32 + x) as u32);
+            let type_id = random as usize % gpu.registry().len();
+            let cell = spawn_cell(type_id as i32, &materials[type_id], random_float(random));
+            grid.set(location, cell);
+        }
+    }
+    let mut cpu = Simulation::new(grid, gpu.registry(), gpu.reactions().clone());
+    cpu.set_frame(gpu.frame());
+    gpu.set_cells(cpu.grid().cells());
+    check(gpu, &mut cpu);
+}
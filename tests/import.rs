@@ -0,0 +1,131 @@
+mod common;
+
+use bevy::math::{IVec2, UVec2};
+use common::{reactions, registry};
+use image::{Rgba, RgbaImage};
+use litterbox::{
+    import::{cells_from_image, ColorMatching, ImageFit, ImportError, Palette},
+    simulation::{Grid, Simulation},
+    SimulationSeed,
+};
+
+fn palette() -> Palette {
+    Palette::from_bytes(include_bytes!("../assets/litterbox.palette.ron"))
+        .expect("palette should parse")
+}
+
+fn type_ids(image: &RgbaImage, size: UVec2, fit: ImageFit, palette: &Palette) -> Vec<i32> {
+    cells_from_image(
+        image,
+        size,
+        fit,
+        palette,
+        &registry(),
+        SimulationSeed::default(),
+    )
+    .expect("image should import")
+    .iter()
+    .map(|cell| cell.type_id)
+    .collect()
+}
+
+#[test]
+fn pixels_take_the_material_of_their_color() {
+    let registry = registry();
+    let [wall, sand, water] = ["Wall", "Sand", "Water"].map(|name| registry.id(name).unwrap());
+    let image = RgbaImage::from_fn(4, 1, |x, _| match x {
+        0 => Rgba([128, 128, 128, 255]),
+        1 => Rgba([230, 190, 110, 255]),
+        2 => Rgba([0, 80, 255, 255]),
+        _ => Rgba([0, 80, 255, 0]),
+    });
+    let size = UVec2::new(4, 1);
+
+    let mut palette = palette();
+    assert_eq!(
+        type_ids(&image, size, ImageFit::Resize, &palette),
+        [wall, sand, water, 0]
+    );
+    palette.matching = ColorMatching::Exact;
+    assert_eq!(
+        type_ids(&image, size, ImageFit::Resize, &palette),
+        [wall, 0, water, 0]
+    );
+}
+
+#[test]
+fn images_are_made_to_fit_the_grid() {
+    let wall = registry().id("Wall").unwrap();
+    let image = RgbaImage::from_pixel(2, 2, Rgba([128, 128, 128, 255]));
+    let size = UVec2::new(4, 4);
+
+    let resized = type_ids(&image, size, ImageFit::Resize, &palette());
+    assert!(resized.iter().all(|&type_id| type_id == wall));
+
+    let cropped = type_ids(&image, size, ImageFit::Crop, &palette());
+    #[rustfmt::skip]
+    assert_eq!(cropped, [
+        0, 0, 0, 0,
+        0, wall, wall, 0,
+        0, wall, wall, 0,
+        0, 0, 0, 0,
+    ]);
+}
+
+#[test]
+fn unknown_materials_are_rejected() {
+    let mut palette = palette();
+    palette.colors[0].material = "Unobtainium".to_owned();
+    let image = RgbaImage::new(1, 1);
+    assert!(matches!(
+        cells_from_image(
+            &image,
+            UVec2::ONE,
+            ImageFit::Resize,
+            &palette,
+            &registry(),
+            SimulationSeed::default()
+        ),
+        Err(ImportError::UnknownMaterial(name)) if name == "Unobtainium"
+    ));
+}
+
+#[test]
+fn imported_cells_vary_with_the_seed_like_init() {
+    let registry = registry();
+    let sand = registry.id("Sand").unwrap();
+    let seed = SimulationSeed(7);
+    let size = UVec2::splat(16);
+    let mut simulation =
+        Simulation::new(Grid::new(size.x, size.y), &registry, reactions(&registry));
+    simulation.set_seed(seed);
+    simulation.init();
+
+    let image = RgbaImage::from_pixel(size.x, size.y, Rgba([230, 190, 110, 255]));
+    let import = |seed| {
+        cells_from_image(&image, size, ImageFit::Resize, &palette(), &registry, seed)
+            .expect("image should import")
+    };
+    let cells = import(seed);
+    // The sand that `init` scatters is the same as that imported in the same cells
+    let mut compared = 0;
+    for (index, imported) in cells.iter().enumerate() {
+        let location = IVec2::new(
+            (index as u32 % size.x) as i32,
+            (index as u32 / size.x) as i32,
+        );
+        let placed = simulation.grid().get(location);
+        if placed.type_id == sand {
+            assert_eq!(imported.lifetime, placed.lifetime);
+            assert_eq!(imported.color, placed.color);
+            compared += 1;
+        }
+    }
+    assert!(compared > 0, "init should have placed some sand");
+
+    let other = import(SimulationSeed(8));
+    assert!(cells
+        .iter()
+        .zip(&other)
+        .any(|(cell, other)| cell.color != other.color));
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use litterbox::{
+    material::{MaterialRegistry, MaterialTable, MaterialTableError},
+    reaction::{ReactionList, ReactionTable, ReactionTableError},
+};
+
+fn table(reactions: &str) -> Result<ReactionTable, ReactionTableError> {
+    let list = ReactionList::from_bytes(format!("(reactions: [{reactions}])").as_bytes())
+        .expect("reactions should parse");
+    ReactionTable::new(&list, &common::registry())
+}
+
+#[test]
+fn reactions_apply_both_ways() {
+    let registry = common::registry();
+    let [water, lava, steam, stone] =
+        ["Water", "Lava", "Steam", "Stone"].map(|name| registry.id(name).unwrap());
+    let table = table(
+        r#"(reactant: "Water", neighbor: "Lava", chance: 0.5, into: Some("Steam"),
+            neighbor_into: Some("Stone"))"#,
+    )
+    .expect("reactions should be valid");
+
+    assert_eq!(table.get(water, lava).chance, 0.5);
+    assert_eq!(table.get(water, lava).into, steam);
+    assert_eq!(table.get(lava, water).into, stone);
+}
+
+#[test]
+fn pairs_are_only_listed_once() {
+    let duplicate = table(
+        r#"(reactant: "Water", neighbor: "Lava", chance: 0.0),
+           (reactant: "Lava", neighbor: "Water", chance: 0.5, into: Some("Stone"))"#,
+    );
+    assert!(
+        matches!(duplicate, Err(ReactionTableError::Duplicate(..))),
+        "a pair listed with a chance of 0 should still count as listed"
+    );
+}
+
+#[test]
+fn flammable_materials_catch_fire() {
+    let registry = common::registry();
+    let [fire, wood, water] = ["Fire", "Wood", "Water"].map(|name| registry.id(name).unwrap());
+    let derived = table("").expect("reactions should be valid");
+
+    assert_eq!(derived.get(fire, wood).chance, registry.flammability(wood));
+    assert_eq!(derived.get(wood, fire).into, fire);
+    assert_eq!(derived.get(fire, water).chance, 0.);
+
+    let listed = table(r#"(reactant: "Wood", neighbor: "Fire", chance: 0.9, into: Some("Smoke"))"#)
+        .expect("reactions should be valid");
+    assert_eq!(
+        listed.get(fire, wood).chance,
+        0.9,
+        "a listed reaction should take the place of the flammability"
+    );
+}
+
+#[test]
+fn flammable_materials_say_what_they_burn_into() {
+    let materials = std::str::from_utf8(include_bytes!("../assets/litterbox.materials.ron"))
+        .unwrap()
+        .replace(r#""Fire""#, r#""Blaze""#);
+    let table = MaterialTable::from_bytes(materials.as_bytes()).expect("materials should parse");
+    let registry =
+        MaterialRegistry::new(table.materials.clone()).expect("materials should be valid");
+    let [blaze, oil] = ["Blaze", "Oil"].map(|name| registry.id(name).unwrap());
+    let reactions = ReactionTable::new(
+        &ReactionList::from_bytes(b"(reactions: [])").unwrap(),
+        &registry,
+    )
+    .expect("reactions should be valid");
+    assert_eq!(reactions.get(blaze, oil).chance, registry.flammability(oil));
+    assert_eq!(reactions.get(oil, blaze).into, blaze);
+
+    let mut materials = table.materials;
+    let oil = materials
+        .iter_mut()
+        .find(|material| material.name == "Oil")
+        .unwrap();
+    oil.burns_into = None;
+    assert!(matches!(
+        MaterialRegistry::new(materials),
+        Err(MaterialTableError::MissingBurnsInto(name)) if name == "Oil"
+    ));
+}
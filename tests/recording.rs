@@ -0,0 +1,43 @@
+use bevy::math::UVec2;
+#[cfg(feature = "differential")]
+use litterbox::{differential::GpuSimulation, speed::MAX_STEPS_PER_FRAME};
+use litterbox::{readback::ImageReadback, recording::write_apng};
+
+#[test]
+fn apng_recordings_have_a_frame_per_step() {
+    let frames: Vec<_> = (0..3)
+        .map(|frame| ImageReadback {
+            frame,
+            size: UVec2::new(2, 2),
+            pixels: [frame as u8 * 100, 0, 0, 255].repeat(4),
+        })
+        .collect();
+    let mut bytes = Vec::new();
+    write_apng(&frames, 2, &mut bytes).unwrap();
+
+    let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+    let info = reader.info();
+    assert_eq!((info.width, info.height), (4, 4));
+    assert_eq!(info.animation_control.unwrap().num_frames, 3);
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    for frame in 0..3 {
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(&pixels[..4], &[frame * 100, 0, 0, 255]);
+    }
+}
+
+// More steps than a frame would take at once, each of which should be read back
+#[cfg(feature = "differential")]
+#[test]
+fn streamed_images_miss_no_step() {
+    let mut gpu = GpuSimulation::new();
+    gpu.step(1);
+    let start = gpu.frame() as usize;
+    let steps = MAX_STEPS_PER_FRAME as u32 + 2;
+    let frames = gpu.step_streamed(steps);
+    assert_eq!(
+        frames,
+        (start + 1..=start + steps as usize).collect::<Vec<_>>()
+    );
+}
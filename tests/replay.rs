@@ -0,0 +1,171 @@
+mod common;
+
+use bevy::math::UVec2;
+use litterbox::{
+    cell::Cell,
+    replay::{DrawUniform, Replay, ReplayError, ReplayEvent, ReplayInput, VERSION},
+    simulation::{random_float, spawn_cell},
+    snapshot::Snapshot,
+    SimulationSeed,
+};
+#[cfg(feature = "differential")]
+use litterbox::{differential::GpuSimulation, GridConfig};
+
+fn snapshot(frame: u64) -> Snapshot {
+    let registry = common::registry();
+    let materials = registry.to_gpu();
+    let cells = (0..24u32)
+        .map(|index| {
+            let type_id = index as usize % registry.len();
+            spawn_cell(type_id as i32, &materials[type_id], random_float(index))
+        })
+        .collect();
+    Snapshot {
+        frame,
+        seed: SimulationSeed(42),
+        size: UVec2::new(6, 4),
+        materials: registry.fingerprint(),
+        cells,
+    }
+}
+
+fn event(frame: u64, input: ReplayInput) -> ReplayEvent {
+    ReplayEvent { frame, input }
+}
+
+fn write(replay: &Replay) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    replay
+        .write_to(&mut bytes)
+        .expect("writing to memory should succeed");
+    bytes
+}
+
+#[test]
+fn replays_round_trip() {
+    let stroke = DrawUniform {
+        start: [1.5, 2.],
+        end: [4., 3.25],
+        radius: 2.,
+        type_id: 3,
+    };
+    let events = vec![
+        event(10, ReplayInput::Seed(SimulationSeed(7))),
+        event(10, ReplayInput::Paint(stroke)),
+        event(10, ReplayInput::Pause(true)),
+        event(11, ReplayInput::Step),
+        event(11, ReplayInput::Material(2)),
+        event(12, ReplayInput::BrushRadius(4.5)),
+        event(12, ReplayInput::Heatmap(true)),
+        event(12, ReplayInput::Speed(0.5)),
+    ];
+    let replay = Replay::new(snapshot(10), events.clone());
+    let loaded = Replay::read_from(write(&replay).as_slice()).expect("replay should load");
+
+    assert_eq!(loaded.events, events);
+    assert_eq!(loaded.snapshot.frame, 10);
+    assert_eq!(loaded.snapshot.seed, SimulationSeed(42));
+    assert_eq!(
+        bytemuck::cast_slice::<Cell, u8>(&loaded.snapshot.cells),
+        bytemuck::cast_slice::<Cell, u8>(&replay.snapshot.cells)
+    );
+}
+
+#[test]
+fn replays_start_at_their_snapshot() {
+    let events = vec![
+        event(12, ReplayInput::Step),
+        event(8, ReplayInput::Seed(SimulationSeed(7))),
+        event(9, ReplayInput::Pause(true)),
+        event(10, ReplayInput::Pause(false)),
+    ];
+    let replay = Replay::new(snapshot(10), events);
+
+    assert_eq!(
+        replay.events,
+        [
+            event(10, ReplayInput::Pause(false)),
+            event(12, ReplayInput::Step),
+        ]
+    );
+    assert_eq!(
+        replay.snapshot.seed,
+        SimulationSeed(7),
+        "the snapshot should carry on with the seed set before it"
+    );
+}
+
+#[test]
+fn bad_replays_are_rejected() {
+    let mut bytes = write(&Replay::new(snapshot(0), Vec::new()));
+    bytes[6..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        Replay::read_from(bytes.as_slice()),
+        Err(ReplayError::UnsupportedVersion(version)) if version == VERSION + 1
+    ));
+
+    assert!(matches!(
+        Replay::read_from(&b"LITTER"[..]),
+        Err(ReplayError::NotAReplay)
+    ));
+
+    for input in [
+        ReplayInput::Speed(f32::NAN),
+        ReplayInput::BrushRadius(f32::INFINITY),
+    ] {
+        let replay = Replay::new(snapshot(0), vec![event(0, input)]);
+        assert!(matches!(
+            Replay::read_from(write(&replay).as_slice()),
+            Err(ReplayError::NotFinite(..))
+        ));
+    }
+}
+
+#[cfg(feature = "differential")]
+#[test]
+fn replays_reproduce_the_session() {
+    let path = std::env::temp_dir().join(format!("litterbox-{}.replay", std::process::id()));
+    let mut gpu = GpuSimulation::with_seed(GridConfig::default(), SimulationSeed(3));
+    gpu.step(2);
+    gpu.record_replay(&path);
+
+    let sand = gpu.registry().id("Sand").unwrap();
+    let stroke = |start, end| DrawUniform {
+        start,
+        end,
+        radius: 4.,
+        type_id: sand,
+    };
+    gpu.paint(stroke([10., 10.], [40., 20.]));
+    gpu.step(5);
+    // Both before the same step
+    gpu.paint(stroke([60., 5.], [60., 40.]));
+    gpu.paint(stroke([90., 30.], [100., 30.]));
+    gpu.step(20);
+    gpu.stop_replay();
+    let frame = gpu.frame();
+    let expected = gpu.cells();
+
+    let replay = Replay::load(&path).expect("replay should load");
+    let strokes = replay
+        .events
+        .iter()
+        .filter(|event| matches!(event.input, ReplayInput::Paint(_)))
+        .count();
+    assert_eq!(strokes, 3);
+
+    // Another seed, which the replay replaces
+    let mut replayed = GpuSimulation::with_seed(GridConfig::default(), SimulationSeed(4));
+    replayed.step(1);
+    replayed.play_replay(&path);
+    assert_eq!(replayed.frame() as u64, replay.snapshot.frame);
+    replayed.step(frame - replayed.frame());
+    assert!(!replayed.is_replaying());
+    let _ = std::fs::remove_file(&path);
+
+    assert!(
+        bytemuck::cast_slice::<Cell, u8>(&replayed.cells())
+            == bytemuck::cast_slice::<Cell, u8>(&expected),
+        "the replay should paint and step the cells exactly as they were"
+    );
+}
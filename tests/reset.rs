@@ -0,0 +1,101 @@
+mod common;
+
+use image::{Rgba, RgbaImage};
+use litterbox::{
+    cell::Cell,
+    differential::{compare, GpuSimulation},
+    import::{cells_from_image, ImageFit, Palette},
+    reset::{Initializer, ResetWorld},
+    simulation::{Grid, InitPattern, Simulation},
+    GridConfig, SimulationSeed,
+};
+
+#[test]
+fn resets_fill_the_grid_anew() {
+    let mut gpu = GpuSimulation::with_seed(GridConfig::default(), SimulationSeed(1));
+    gpu.step(12);
+
+    let seed = SimulationSeed(9);
+    gpu.reset(ResetWorld {
+        seed: Some(seed),
+        initializer: Some(Initializer::Walls),
+    });
+    assert_eq!(gpu.frame(), 0);
+    assert_eq!(gpu.seed(), seed);
+
+    let size = gpu.size();
+    let mut cpu = Simulation::new(
+        Grid::new(size.x, size.y),
+        gpu.registry(),
+        gpu.reactions().clone(),
+    );
+    cpu.set_seed(seed);
+    cpu.set_init_pattern(InitPattern::Walls);
+    cpu.init();
+    if let Err(divergence) = compare(cpu.frame(), cpu.grid(), &gpu.cells(), gpu.registry()) {
+        panic!("{divergence}");
+    }
+
+    gpu.step(4);
+    for _ in 0..4 {
+        cpu.step();
+    }
+    if let Err(divergence) = compare(cpu.frame(), cpu.grid(), &gpu.cells(), gpu.registry()) {
+        panic!("{divergence}");
+    }
+}
+
+#[test]
+fn empty_resets_leave_nothing() {
+    let mut gpu = GpuSimulation::new();
+    gpu.step(3);
+    gpu.reset(ResetWorld {
+        initializer: Some(Initializer::Empty),
+        ..Default::default()
+    });
+    assert_eq!(gpu.frame(), 0);
+    assert!(gpu.cells().iter().all(|cell| cell.type_id == 0));
+}
+
+#[test]
+fn image_resets_start_from_the_image() {
+    let mut gpu = GpuSimulation::new();
+    gpu.step(5);
+
+    // Walls on the left and sand on the right
+    let image = RgbaImage::from_fn(8, 8, |x, _| {
+        if x < 4 {
+            Rgba([128, 128, 128, 255])
+        } else {
+            Rgba([230, 190, 110, 255])
+        }
+    });
+    let path = std::env::temp_dir().join(format!("litterbox-reset-{}.png", std::process::id()));
+    image.save(&path).expect("image should save");
+
+    let seed = SimulationSeed(3);
+    gpu.reset(ResetWorld {
+        seed: Some(seed),
+        initializer: Some(Initializer::Image(path.clone())),
+    });
+    std::fs::remove_file(&path).ok();
+    assert_eq!(gpu.frame(), 0);
+    assert_eq!(gpu.seed(), seed);
+
+    let palette = Palette::from_bytes(include_bytes!("../assets/litterbox.palette.ron"))
+        .expect("palette should parse");
+    let registry = common::registry();
+    let expected = cells_from_image(
+        &image,
+        gpu.size(),
+        ImageFit::Resize,
+        &palette,
+        &registry,
+        seed,
+    )
+    .expect("image should import");
+    assert_eq!(
+        bytemuck::cast_slice::<Cell, u8>(&gpu.cells()),
+        bytemuck::cast_slice::<Cell, u8>(&expected)
+    );
+}
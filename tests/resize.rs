@@ -0,0 +1,78 @@
+use bevy::math::{IVec2, UVec2};
+use litterbox::{
+    differential::{compare, GpuSimulation},
+    resize::ResizeAnchor,
+    simulation::{random_float, spawn_cell, Grid, Simulation},
+};
+
+const STEPS: u32 = 10;
+
+#[test]
+fn anchors_keep_their_side_of_the_grid_in_place() {
+    let (from, to) = (UVec2::new(10, 6), UVec2::new(4, 9));
+    assert_eq!(ResizeAnchor::TopLeft.offset(from, to), IVec2::new(0, 0));
+    assert_eq!(ResizeAnchor::Center.offset(from, to), IVec2::new(-3, 1));
+    assert_eq!(ResizeAnchor::Right.offset(from, to), IVec2::new(-6, 1));
+    assert_eq!(
+        ResizeAnchor::BottomRight.offset(from, to),
+        IVec2::new(-6, 3)
+    );
+}
+
+#[test]
+fn resizing_keeps_the_cells_that_overlap() {
+    let mut gpu = GpuSimulation::new();
+    gpu.step(STEPS);
+
+    for (size, anchor) in [
+        (UVec2::new(150, 90), ResizeAnchor::Center),
+        (UVec2::new(61, 100), ResizeAnchor::BottomRight),
+        (UVec2::new(200, 20), ResizeAnchor::Top),
+    ] {
+        let from = Grid::from_cells(gpu.size(), gpu.cells());
+        gpu.resize(size, anchor);
+
+        // Whatever isn't carried over is empty
+        let offset = anchor.offset(UVec2::new(from.width(), from.height()), size);
+        let empty = gpu.registry().to_gpu()[0];
+        let mut grid = Grid::new(size.x, size.y);
+        for y in 0..size.y as i32 {
+            for x in 0..size.x as i32 {
+                let location = IVec2::new(x, y);
+                let cell = if from.in_bounds(location - offset) {
+                    from.get(location - offset)
+                } else {
+                    spawn_cell(0, &empty, random_float((y * size.x as i32 + x) as u32))
+                };
+                grid.set(location, cell);
+            }
+        }
+        if let Err(divergence) = compare(gpu.frame(), &grid, &gpu.cells(), gpu.registry()) {
+            panic!("{divergence}");
+        }
+
+        // and the resized grid carries on like any other
+        let mut cpu = Simulation::new(grid, gpu.registry(), gpu.reactions().clone());
+        cpu.set_frame(gpu.frame());
+        gpu.step(STEPS);
+        for _ in 0..STEPS {
+            cpu.step();
+        }
+        if let Err(divergence) = compare(cpu.frame(), cpu.grid(), &gpu.cells(), gpu.registry()) {
+            panic!("{divergence}");
+        }
+    }
+}
+
+#[test]
+fn sizes_the_gpu_cant_hold_are_turned_down() {
+    let mut gpu = GpuSimulation::new();
+    gpu.step(1);
+    let size = gpu.size();
+    gpu.resize(UVec2::splat(1 << 20), ResizeAnchor::Center);
+    assert_eq!(gpu.size(), size);
+
+    // and the grid carries on
+    gpu.step(1);
+    assert_eq!(gpu.cells().len(), size.x as usize * size.y as usize);
+}
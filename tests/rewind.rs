@@ -0,0 +1,51 @@
+use litterbox::{
+    cell::Cell, differential::GpuSimulation, rewind::RewindConfig, GridConfig, SimulationSeed,
+};
+
+fn bytes(cells: &[Cell]) -> &[u8] {
+    bytemuck::cast_slice(cells)
+}
+
+#[test]
+fn rewinding_takes_the_same_steps_again() {
+    let mut gpu = GpuSimulation::with_seed(GridConfig::default(), SimulationSeed(5));
+    gpu.step(10);
+    let at_10 = gpu.cells();
+    gpu.step(7);
+    let at_17 = gpu.cells();
+    assert!(gpu.rewind_steps().contains(&10));
+
+    gpu.rewind(10);
+    assert_eq!(gpu.frame(), 10);
+    assert!(
+        bytes(&gpu.cells()) == bytes(&at_10),
+        "rewinding should bring back the cells as they were"
+    );
+    assert!(
+        gpu.rewind_steps().contains(&17),
+        "the steps after it should be kept until they are taken again"
+    );
+
+    gpu.step(7);
+    assert_eq!(gpu.frame(), 17);
+    assert!(
+        bytes(&gpu.cells()) == bytes(&at_17),
+        "stepping on should take the same steps as before"
+    );
+
+    gpu.step_back();
+    assert_eq!(gpu.frame(), 16);
+    assert!(gpu.rewind_steps().contains(&17));
+}
+
+#[test]
+fn large_grids_keep_fewer_steps() {
+    let config = RewindConfig {
+        depth: 32,
+        interval: 1,
+        memory_budget: 1 << 20,
+    };
+    assert_eq!(config.depth_within_budget(1 << 10), 32);
+    assert_eq!(config.depth_within_budget(1 << 17), 8);
+    assert_eq!(config.depth_within_budget(1 << 30), 1);
+}
@@ -0,0 +1,20 @@
+use bevy::math::UVec2;
+use litterbox::{readback::ImageReadback, screenshot::screenshot_image};
+
+#[test]
+fn screenshots_are_opaque_and_upscaled_without_blending() {
+    let readback = ImageReadback {
+        frame: 0,
+        size: UVec2::new(2, 1),
+        pixels: vec![255, 128, 0, 255, 200, 100, 50, 0],
+    };
+
+    let image = screenshot_image(&readback, 1);
+    assert_eq!(image.dimensions(), (2, 1));
+    assert_eq!(image.as_raw(), &[255, 128, 0, 255, 0, 0, 0, 255]);
+
+    let upscaled = screenshot_image(&readback, 3);
+    assert_eq!(upscaled.dimensions(), (6, 3));
+    assert_eq!(upscaled.get_pixel(2, 2).0, [255, 128, 0, 255]);
+    assert_eq!(upscaled.get_pixel(3, 0).0, [0, 0, 0, 255]);
+}
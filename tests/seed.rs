@@ -0,0 +1,65 @@
+mod common;
+
+use litterbox::{
+    cell::Cell,
+    simulation::{Grid, Simulation},
+    SimulationSeed,
+};
+#[cfg(feature = "differential")]
+use litterbox::{
+    differential::{compare, GpuSimulation},
+    GridConfig,
+};
+
+const STEPS: u32 = 25;
+#[cfg(feature = "differential")]
+const CHECKS: u32 = 2;
+
+// The cells after filling the grid like `init` does and taking `STEPS` steps
+fn run(seed: SimulationSeed) -> Vec<u8> {
+    let registry = common::registry();
+    let reactions = common::reactions(&registry);
+
+    let mut simulation = Simulation::new(Grid::new(64, 64), &registry, reactions);
+    simulation.set_seed(seed);
+    simulation.init();
+    for _ in 0..STEPS {
+        simulation.step();
+    }
+    bytemuck::cast_slice::<Cell, u8>(simulation.grid().cells()).to_vec()
+}
+
+#[test]
+fn runs_repeat_with_the_same_seed() {
+    assert!(run(SimulationSeed(7)) == run(SimulationSeed(7)));
+    assert!(
+        run(SimulationSeed(7)) != run(SimulationSeed(8)),
+        "another seed should fill and step the grid differently"
+    );
+}
+
+#[cfg(feature = "differential")]
+#[test]
+fn seeded_compute_shaders_match_the_cpu_simulation() {
+    let seed = SimulationSeed(0x5eed);
+    let mut gpu = GpuSimulation::with_seed(GridConfig::default(), seed);
+    assert_eq!(gpu.seed(), seed);
+    let size = gpu.size();
+
+    let mut cpu = Simulation::new(
+        Grid::new(size.x, size.y),
+        gpu.registry(),
+        gpu.reactions().clone(),
+    );
+    cpu.set_seed(seed);
+    cpu.init();
+    for _ in 0..CHECKS {
+        gpu.step(STEPS);
+        for _ in 0..STEPS {
+            cpu.step();
+        }
+        if let Err(divergence) = compare(cpu.frame(), cpu.grid(), &gpu.cells(), gpu.registry()) {
+            panic!("{divergence}");
+        }
+    }
+}
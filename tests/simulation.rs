@@ -0,0 +1,97 @@
+mod common;
+
+use bevy::math::IVec2;
+use litterbox::{
+    material::MaterialRegistry,
+    simulation::{Grid, Simulation},
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+fn simulation(grid: Grid) -> (Simulation, MaterialRegistry) {
+    let registry = common::registry();
+    let reactions = common::reactions(&registry);
+    (Simulation::new(grid, &registry, reactions), registry)
+}
+
+#[test]
+fn init_builds_walls_and_scatters_sand() {
+    let (mut simulation, registry) = simulation(Grid::new(WIDTH, HEIGHT));
+    simulation.init();
+
+    let wall = registry.id("Wall").unwrap();
+    let sand = registry.id("Sand").unwrap();
+    let grid = simulation.grid();
+    for y in 0..HEIGHT as i32 {
+        for x in 0..WIDTH as i32 {
+            let type_id = grid.get(IVec2::new(x, y)).type_id;
+            if x == 0 || x == WIDTH as i32 - 1 || y == HEIGHT as i32 / 2 - 1 {
+                assert_eq!(type_id, wall, "expected a wall at ({x}, {y})");
+            } else {
+                assert!(
+                    type_id == 0 || type_id == sand,
+                    "unexpected material at ({x}, {y})"
+                );
+            }
+        }
+    }
+    let sand_fraction = grid.count(sand) as f32 / (WIDTH * HEIGHT) as f32;
+    assert!((0.05..0.15).contains(&sand_fraction), "{sand_fraction}");
+}
+
+#[test]
+fn sand_comes_to_rest_on_the_wall() {
+    let (mut simulation, registry) = simulation(Grid::new(WIDTH, HEIGHT));
+    simulation.init();
+    for _ in 0..500 {
+        simulation.step();
+    }
+
+    let sand = registry.id("Sand").unwrap();
+    let grid = simulation.grid();
+    for y in 0..HEIGHT as i32 - 1 {
+        for x in 0..WIDTH as i32 {
+            if grid.get(IVec2::new(x, y)).type_id == sand {
+                let below = grid.get(IVec2::new(x, y + 1)).type_id;
+                assert_ne!(below, 0, "sand at ({x}, {y}) is still falling");
+            }
+        }
+    }
+}
+
+#[test]
+fn heat_spreads_without_being_lost() {
+    let mut grid = Grid::new(WIDTH, HEIGHT);
+    let (_, registry) = simulation(grid.clone());
+    let wall = registry.id("Wall").unwrap();
+    for y in 0..HEIGHT as i32 {
+        for x in 0..WIDTH as i32 {
+            let location = IVec2::new(x, y);
+            let mut cell = grid.get(location);
+            cell.type_id = wall;
+            grid.set(location, cell);
+        }
+    }
+    let center = IVec2::new(WIDTH as i32 / 2, HEIGHT as i32 / 2);
+    let mut cell = grid.get(center);
+    cell.temperature = 5000.;
+    grid.set(center, cell);
+
+    let total_heat = |grid: &Grid| {
+        grid.cells()
+            .iter()
+            .map(|cell| cell.temperature as f64)
+            .sum::<f64>()
+    };
+    let initial_heat = total_heat(&grid);
+
+    let (mut simulation, _) = simulation(grid);
+    for _ in 0..200 {
+        simulation.step();
+    }
+    let grid = simulation.grid();
+    assert!((total_heat(grid) - initial_heat).abs() / initial_heat < 1e-3);
+    assert!(grid.get(center).temperature < 1000.);
+    assert!(grid.get(center + IVec2::new(3, 0)).temperature > 30.);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use bevy::math::UVec2;
+use common::registry;
+use litterbox::{
+    cell::Cell,
+    material::MaterialRegistry,
+    simulation::{random_float, spawn_cell},
+    snapshot::{Snapshot, SnapshotError, VERSION},
+    SimulationSeed,
+};
+
+fn snapshot(registry: &MaterialRegistry) -> Snapshot {
+    let materials = registry.to_gpu();
+    let cells = (0..24u32)
+        .map(|index| {
+            let type_id = index as usize % registry.len();
+            spawn_cell(type_id as i32, &materials[type_id], random_float(index))
+        })
+        .collect();
+    Snapshot {
+        frame: 1234,
+        seed: SimulationSeed(42),
+        size: UVec2::new(6, 4),
+        materials: registry.fingerprint(),
+        cells,
+    }
+}
+
+fn write(snapshot: &Snapshot) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    snapshot
+        .write_to(&mut bytes)
+        .expect("writing to memory should succeed");
+    bytes
+}
+
+#[test]
+fn snapshots_round_trip() {
+    let registry = registry();
+    let snapshot = snapshot(&registry);
+    let loaded = Snapshot::read_from(write(&snapshot).as_slice()).expect("snapshot should load");
+
+    assert_eq!(loaded.frame, snapshot.frame);
+    assert_eq!(loaded.seed, snapshot.seed);
+    assert_eq!(loaded.size, snapshot.size);
+    assert_eq!(loaded.materials, snapshot.materials);
+    assert_eq!(
+        bytemuck::cast_slice::<Cell, u8>(&loaded.cells),
+        bytemuck::cast_slice::<Cell, u8>(&snapshot.cells)
+    );
+    assert!(loaded.check(snapshot.size, &registry).is_ok());
+}
+
+#[test]
+fn bad_snapshots_are_rejected() {
+    let registry = registry();
+    let snapshot = snapshot(&registry);
+    let bytes = write(&snapshot);
+
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    assert!(matches!(
+        Snapshot::read_from(wrong_magic.as_slice()),
+        Err(SnapshotError::NotASnapshot)
+    ));
+
+    let mut newer = bytes.clone();
+    newer[6..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        Snapshot::read_from(newer.as_slice()),
+        Err(SnapshotError::UnsupportedVersion(version)) if version == VERSION + 1
+    ));
+
+    let mut larger = bytes.clone();
+    larger[10..14].copy_from_slice(&7u32.to_le_bytes());
+    assert!(matches!(
+        Snapshot::read_from(larger.as_slice()),
+        Err(SnapshotError::Truncated(7, 4))
+    ));
+
+    assert!(matches!(
+        snapshot.check(UVec2::new(8, 8), &registry),
+        Err(SnapshotError::SizeMismatch(8, 8, 6, 4))
+    ));
+    let other = Snapshot {
+        materials: snapshot.materials ^ 1,
+        ..snapshot
+    };
+    assert!(matches!(
+        other.check(other.size, &registry),
+        Err(SnapshotError::MaterialsChanged)
+    ));
+}
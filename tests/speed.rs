@@ -0,0 +1,45 @@
+use litterbox::speed::{SimulationSpeed, MAX_STEPS_PER_SECOND, MIN_STEPS_PER_SECOND};
+
+#[test]
+fn speeds_stay_within_range() {
+    assert_eq!(
+        SimulationSpeed::new(1000.).steps_per_second,
+        MAX_STEPS_PER_SECOND
+    );
+    assert_eq!(
+        SimulationSpeed::new(0.).steps_per_second,
+        MIN_STEPS_PER_SECOND
+    );
+    assert_eq!(
+        SimulationSpeed::new(MAX_STEPS_PER_SECOND).faster(),
+        SimulationSpeed::new(MAX_STEPS_PER_SECOND)
+    );
+    assert_eq!(SimulationSpeed::new(2.).slower().steps_per_second, 1.);
+    assert_eq!(
+        SimulationSpeed::new(f32::INFINITY).steps_per_second,
+        MAX_STEPS_PER_SECOND
+    );
+    assert_eq!(SimulationSpeed::new(f32::NAN), SimulationSpeed::default());
+}
+
+#[test]
+fn slider_fractions_round_trip() {
+    assert_eq!(
+        SimulationSpeed::from_fraction(0.).steps_per_second,
+        MIN_STEPS_PER_SECOND
+    );
+    assert_eq!(
+        SimulationSpeed::from_fraction(1.).steps_per_second,
+        MAX_STEPS_PER_SECOND
+    );
+    for steps_per_second in [0.25, 1., 2., 30., 256.] {
+        let speed = SimulationSpeed::new(steps_per_second);
+        let fraction = speed.fraction();
+        assert!((0. ..=1.).contains(&fraction));
+        let back = SimulationSpeed::from_fraction(fraction).steps_per_second;
+        assert!(
+            (back - steps_per_second).abs() <= 1e-3 * steps_per_second,
+            "{steps_per_second} steps/s came back as {back}"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use bevy::{
+    math::{IVec2, UVec2},
+    utils::default,
+};
+use litterbox::{
+    chunk::CHUNK_SIZE,
+    differential::{compare, GpuSimulation},
+    simulation::{random_float, spawn_cell, Grid, Simulation},
+    GridConfig,
+};
+
+const STEPS: u32 = 25;
+const CHECKS: u32 = 6;
+
+#[test]
+fn sleeping_tiles_match_the_cpu_simulation() {
+    let config = GridConfig {
+        size: UVec2::new(2 * CHUNK_SIZE, CHUNK_SIZE),
+        ..default()
+    };
+    let mut gpu = GpuSimulation::with_config(config);
+    gpu.step(1);
+    let size = gpu.size();
+
+    // A column of sand falling onto a floor in one corner, that the far tiles never see
+    let mut grid = Grid::new(size.x, size.y);
+    let materials = gpu.registry().to_gpu();
+    let wall = gpu.registry().id("Wall").unwrap();
+    let sand = gpu.registry().id("Sand").unwrap();
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            let type_id = if y == size.y as i32 - 1 {
+                wall
+            } else if x < 8 && y < 24 {
+                sand
+            } else {
+                continue;
+            };
+            let random = random_float((y * size.x as i32 + x) as u32);
+            let cell = spawn_cell(type_id, &materials[type_id as usize], random);
+            grid.set(IVec2::new(x, y), cell);
+        }
+    }
+    let mut cpu = Simulation::new(grid, gpu.registry(), gpu.reactions().clone());
+    cpu.set_frame(gpu.frame());
+    gpu.set_cells(cpu.grid().cells());
+
+    for check in 0..CHECKS {
+        gpu.step(STEPS);
+        for _ in 0..STEPS {
+            cpu.step();
+        }
+        if let Err(divergence) = compare(cpu.frame(), cpu.grid(), &gpu.cells(), gpu.registry()) {
+            panic!("{divergence}");
+        }
+        if check == 0 {
+            assert!(
+                (gpu.awake_tiles() as usize) < config.num_tiles() / 2,
+                "the tiles away from the sand should be asleep"
+            );
+        }
+    }
+}